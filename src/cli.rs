@@ -0,0 +1,396 @@
+use crate::crawler::DepthMode;
+use crate::findings::FailOn;
+use crate::normalize::NormalizeStep;
+use crate::output::Format;
+use crate::report::{ReportFormat, StatusClass};
+use crate::config;
+use crate::diff::DiffFormat;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use regex::Regex;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use url::Url;
+
+#[derive(Parser, Debug)]
+#[command(name = "check404", version, about = "Crawl a site and report broken links")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Crawl one or more sites and check every link (default)
+    Crawl(Box<CrawlArgs>),
+    /// Show broken links saved in crawl_data.db by earlier crawls
+    Report(ReportArgs),
+    /// Compare two runs saved in crawl_data.db: newly broken, fixed, redirected and disappeared URLs
+    Diff(DiffArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct CrawlArgs {
+    /// The starting URLs to crawl
+    #[arg(value_name = "URL", default_value = "http://localhost/", value_parser = parse_url)]
+    pub start_urls: Vec<Url>,
+
+    /// The maximum depth to crawl
+    #[arg(short = 'd', long, default_value_t = 3)]
+    pub depth: u32,
+
+    /// How depth is counted: hops (links followed from a start URL) or path (segments in the URL path; stored hop counts are then not always the shortest)
+    #[arg(long, value_enum, default_value_t = DepthMode::Hops)]
+    pub depth_mode: DepthMode,
+
+    /// Extra URL normalization steps to apply when deduplicating URLs, e.g. lowercase-path (comma separated or repeated)
+    #[arg(long, value_enum, value_name = "STEPS", value_delimiter = ',')]
+    pub normalize: Vec<NormalizeStep>,
+
+    /// URL normalization steps to turn off: lowercase-host, default-port, percent-encoding, sort-query, tracking-params, index-file, trailing-slash
+    #[arg(long, value_enum, value_name = "STEPS", value_delimiter = ',')]
+    pub no_normalize: Vec<NormalizeStep>,
+
+    /// File containing URL patterns to match (one regex per line, optionally followed by limit=N; named captures like (?P<id>[0-9]+) group URLs by template)
+    #[arg(short = 'x', long, value_name = "PATTERN_FILE")]
+    pub patterns: Option<PathBuf>,
+
+    /// Maximum number of URLs to crawl per pattern unless the pattern sets its own limit
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub pattern_limit: usize,
+
+    /// Config file with crawl profiles (default: check404.toml, check404.yaml or check404.yml if present)
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Profile in the config file to use (default: "default")
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Only crawl internal URLs matching this regex (can be repeated)
+    #[arg(long, value_name = "REGEX")]
+    pub include: Vec<Regex>,
+
+    /// Do not crawl or check URLs matching this regex (can be repeated)
+    #[arg(long, value_name = "REGEX")]
+    pub exclude: Vec<Regex>,
+
+    /// Number of concurrent workers
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    pub concurrency: u32,
+
+    /// Maximum requests per second per host (default: unlimited)
+    #[arg(long, value_name = "RPS", value_parser = parse_positive_f64)]
+    pub rate: Option<f64>,
+
+    /// Minimum delay between requests to the same host in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    pub delay: u64,
+
+    /// Do not fetch or honor robots.txt
+    #[arg(long, overrides_with = "respect_robots")]
+    pub ignore_robots: bool,
+
+    /// Fetch and honor robots.txt even if the profile sets ignore_robots (default)
+    #[arg(long, overrides_with = "ignore_robots")]
+    pub respect_robots: bool,
+
+    /// Check the status of links to other sites without crawling them
+    #[arg(long, overrides_with = "no_check_external")]
+    pub check_external: bool,
+
+    /// Do not check links to other sites even if the profile sets check_external (default)
+    #[arg(long, overrides_with = "check_external")]
+    pub no_check_external: bool,
+
+    /// Report redirect chains longer than this many hops
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    pub max_redirects: usize,
+
+    /// Retry 429/5xx responses, timeouts and connection errors up to N times
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    pub retries: u32,
+
+    /// Base delay for exponential backoff between retries in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    pub retry_delay: u64,
+
+    /// Do not randomize the delay between retries
+    #[arg(long, overrides_with = "retry_jitter")]
+    pub no_retry_jitter: bool,
+
+    /// Randomize the delay between retries even if the profile sets retry_jitter = false (default)
+    #[arg(long, overrides_with = "no_retry_jitter")]
+    pub retry_jitter: bool,
+
+    /// Timeout for establishing a connection in seconds
+    #[arg(long, value_name = "SECS", default_value_t = 10.0, value_parser = parse_positive_f64)]
+    pub connect_timeout: f64,
+
+    /// Timeout for each read from the connection in seconds
+    #[arg(long, value_name = "SECS", default_value_t = 30.0, value_parser = parse_positive_f64)]
+    pub read_timeout: f64,
+
+    /// Total timeout for a request in seconds
+    #[arg(long, value_name = "SECS", default_value_t = 60.0, value_parser = parse_positive_f64)]
+    pub timeout: f64,
+
+    /// User-Agent header to send (default: check404/<version>)
+    #[arg(long, value_name = "UA")]
+    pub user_agent: Option<String>,
+
+    /// How long idle keep-alive connections are kept in seconds
+    #[arg(long, value_name = "SECS", default_value_t = 90.0, value_parser = parse_positive_f64)]
+    pub pool_idle_timeout: f64,
+
+    /// Maximum idle keep-alive connections per host
+    #[arg(long, value_name = "N", default_value_t = 16)]
+    pub pool_max_idle: usize,
+
+    /// Exit with a non-zero status when these errors are found: 404, 4xx, 5xx, redirect, network (timeouts, connection and body errors), all (comma separated)
+    #[arg(long, value_name = "CLASSES", default_value = "none", value_parser = FailOn::parse)]
+    pub fail_on: FailOn,
+
+    /// Output format of the report: text, json, ndjson or junit (progress goes to standard error unless text)
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+
+    /// Also write a self-contained HTML report to this file
+    #[arg(long, value_name = "FILE")]
+    pub html_report: Option<PathBuf>,
+
+    /// Write the report to this file instead of standard output
+    #[arg(short = 'o', long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// SQLite database file to store the results in
+    #[arg(long, value_name = "FILE", default_value = "crawl_data.db")]
+    pub db: PathBuf,
+
+    /// Do not store the results in a database (they only appear in the report)
+    #[arg(long, conflicts_with = "db")]
+    pub no_db: bool,
+
+    /// Continue an interrupted run without refetching the URLs it already checked (its start URLs are reused; depth, depth mode, normalization, patterns, include/exclude, check-external and ignore-robots must match the original run)
+    #[arg(long, value_name = "RUN_ID", conflicts_with = "no_db")]
+    pub resume: Option<i64>,
+
+    /// With --resume, also continue a run still marked "running" (left behind by a crashed or killed check404; make sure no other process is crawling it)
+    #[arg(long, requires = "resume")]
+    pub force_resume: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ReportArgs {
+    /// Also list URLs without errors (text format only; csv always lists every page)
+    #[arg(long)]
+    pub all: bool,
+
+    /// Show the results of this run (default: the latest run)
+    #[arg(long, value_name = "RUN_ID")]
+    pub run: Option<i64>,
+
+    /// List past runs instead of showing results
+    #[arg(long)]
+    pub list_runs: bool,
+
+    /// Output format: text or csv
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    pub format: ReportFormat,
+
+    /// Only include pages with these statuses: e.g. 404, 4xx, 5xx, network (comma separated or repeated)
+    #[arg(long, value_name = "CLASSES", value_delimiter = ',', value_parser = StatusClass::parse)]
+    pub status: Vec<StatusClass>,
+
+    /// Only include pages on these domains (comma separated or repeated)
+    #[arg(long, value_name = "DOMAINS", value_delimiter = ',')]
+    pub domain: Vec<String>,
+
+    /// SQLite database file to read the results from
+    #[arg(long, value_name = "FILE", default_value = "crawl_data.db")]
+    pub db: PathBuf,
+}
+
+impl CrawlArgs {
+    // runs.options に保存するクロールの設定
+    pub fn options_json(&self) -> String {
+        serde_json::json!({
+            "depth": self.depth,
+            "depth_mode": self.depth_mode,
+            "normalize": self.normalize,
+            "no_normalize": self.no_normalize,
+            "patterns": self.patterns,
+            "pattern_limit": self.pattern_limit,
+            "profile": self.profile,
+            "include": self.include.iter().map(Regex::as_str).collect::<Vec<_>>(),
+            "exclude": self.exclude.iter().map(Regex::as_str).collect::<Vec<_>>(),
+            "concurrency": self.concurrency,
+            "rate": self.rate,
+            "delay": self.delay,
+            "ignore_robots": self.ignore_robots,
+            "check_external": self.check_external,
+            "max_redirects": self.max_redirects,
+            "retries": self.retries,
+            "retry_delay": self.retry_delay,
+            "timeout": self.timeout,
+            "user_agent": self.user_agent,
+        })
+        .to_string()
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    /// The older run (default: the latest finished run before NEW_RUN)
+    #[arg(value_name = "OLD_RUN")]
+    pub old_run: Option<i64>,
+
+    /// The newer run (default: the latest finished run; running and interrupted runs are skipped)
+    #[arg(value_name = "NEW_RUN")]
+    pub new_run: Option<i64>,
+
+    /// Output format: text or json
+    #[arg(long, value_enum, default_value_t = DiffFormat::Text)]
+    pub format: DiffFormat,
+
+    /// Exit with a non-zero status when there are newly broken URLs
+    #[arg(long)]
+    pub fail_on_new: bool,
+
+    /// SQLite database file to read the runs from
+    #[arg(long, value_name = "FILE", default_value = "crawl_data.db")]
+    pub db: PathBuf,
+}
+
+const SUBCOMMANDS: [&str; 4] = ["crawl", "report", "diff", "help"];
+
+impl Cli {
+    // サブコマンドが省略された場合は crawl として扱う（check404 <URL> -d=3 の互換のため）
+    // crawl では設定ファイルのプロファイルを読み込み、コマンドラインで指定しなかった値を埋める
+    pub fn parse_from_env() -> Result<Cli, String> {
+        let matches = Cli::command().get_matches_from(with_default_subcommand(std::env::args_os().collect()));
+        let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        if let (Command::Crawl(args), Some(crawl_matches)) = (&mut cli.command, matches.subcommand_matches("crawl")) {
+            config::apply(args, &|id| is_explicit(crawl_matches, id))?;
+        }
+        Ok(cli)
+    }
+
+    #[cfg(test)]
+    pub fn try_parse_args(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(with_default_subcommand(args.iter().map(OsString::from).collect()))
+    }
+}
+
+fn with_default_subcommand(mut args: Vec<OsString>) -> Vec<OsString> {
+    let first = args.get(1).and_then(|arg| arg.to_str()).unwrap_or("");
+    let is_top_level = SUBCOMMANDS.contains(&first) || matches!(first, "-h" | "--help" | "-V" | "--version");
+    if !is_top_level {
+        args.insert(1, OsString::from("crawl"));
+    }
+    args
+}
+
+// コマンドラインで指定された引数かどうか（デフォルト値は含まない）
+fn is_explicit(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+}
+
+pub(crate) fn parse_url(value: &str) -> Result<Url, String> {
+    let url = Url::parse(value).map_err(|e| format!("invalid URL: {}", e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("unsupported scheme: {}", url.scheme()));
+    }
+    Ok(url)
+}
+
+fn parse_positive_f64(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(number) if number > 0.0 && number.is_finite() => Ok(number),
+        _ => Err(format!("expected a positive number, got {}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crawl_args(args: &[&str]) -> CrawlArgs {
+        match Cli::try_parse_args(args).unwrap().command {
+            Command::Crawl(args) => *args,
+            command => panic!("unexpected command: {:?}", command),
+        }
+    }
+
+    #[test]
+    fn test_depth_parsing() {
+        // デフォルト値のテスト
+        assert_eq!(crawl_args(&["check404"]).depth, 3);
+
+        // 旧来の -d= と新しい --depth の両方を受け付ける
+        assert_eq!(crawl_args(&["check404", "-d=5"]).depth, 5);
+        assert_eq!(crawl_args(&["check404", "http://example.com", "-d=2"]).depth, 2);
+        assert_eq!(crawl_args(&["check404", "crawl", "--depth", "4", "http://example.com"]).depth, 4);
+
+        // 不正な値や負の値はエラーにする
+        assert!(Cli::try_parse_args(&["check404", "-d=invalid"]).is_err());
+        assert!(Cli::try_parse_args(&["check404", "-d=-1"]).is_err());
+    }
+
+    #[test]
+    fn test_pattern_file_parsing() {
+        assert_eq!(crawl_args(&["check404"]).patterns, None);
+        assert_eq!(crawl_args(&["check404", "-x=patterns.txt"]).patterns, Some(PathBuf::from("patterns.txt")));
+        assert_eq!(crawl_args(&["check404", "--patterns", "patterns.txt"]).patterns, Some(PathBuf::from("patterns.txt")));
+    }
+
+    #[test]
+    fn test_multiple_start_urls() {
+        let args = crawl_args(&["check404", "http://example.com/", "https://example.org/docs", "--concurrency=8"]);
+        let urls: Vec<&str> = args.start_urls.iter().map(Url::as_str).collect();
+        assert_eq!(urls, vec!["http://example.com/", "https://example.org/docs"]);
+        assert_eq!(args.concurrency, 8);
+
+        assert_eq!(crawl_args(&["check404"]).start_urls, vec![Url::parse("http://localhost/").unwrap()]);
+        assert!(Cli::try_parse_args(&["check404", "not a url"]).is_err());
+        assert!(Cli::try_parse_args(&["check404", "ftp://example.com/"]).is_err());
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        assert!(Cli::try_parse_args(&["check404", "--concurrency=0"]).is_err());
+        assert!(Cli::try_parse_args(&["check404", "--rate=-2"]).is_err());
+        assert!(Cli::try_parse_args(&["check404", "--fail-on=3xx"]).is_err());
+    }
+
+    #[test]
+    fn test_report_subcommand() {
+        let Command::Report(args) = Cli::try_parse_args(&["check404", "report", "--all"]).unwrap().command else {
+            panic!("expected report");
+        };
+        assert!(args.all);
+        assert_eq!(args.format, ReportFormat::Text);
+
+        let Command::Report(args) = Cli::try_parse_args(&["check404", "report", "--format", "csv", "--status", "4xx,network", "--domain", "example.com"]).unwrap().command else {
+            panic!("expected report");
+        };
+        assert_eq!(args.format, ReportFormat::Csv);
+        assert_eq!(args.status, vec![StatusClass::Class(4), StatusClass::Network]);
+        assert_eq!(args.domain, vec!["example.com".to_string()]);
+        assert!(Cli::try_parse_args(&["check404", "report", "--status", "7xx"]).is_err());
+    }
+
+    #[test]
+    fn test_diff_subcommand() {
+        let Command::Diff(args) = Cli::try_parse_args(&["check404", "diff", "3", "5", "--format", "json"]).unwrap().command else {
+            panic!("expected diff");
+        };
+        assert_eq!((args.old_run, args.new_run), (Some(3), Some(5)));
+        assert_eq!(args.format, DiffFormat::Json);
+
+        let Command::Diff(args) = Cli::try_parse_args(&["check404", "diff"]).unwrap().command else {
+            panic!("expected diff");
+        };
+        assert_eq!((args.old_run, args.new_run), (None, None));
+    }
+}
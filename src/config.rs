@@ -0,0 +1,381 @@
+use crate::cli::{self, CrawlArgs};
+use crate::crawler::DepthMode;
+use crate::findings::FailOn;
+use crate::normalize::NormalizeStep;
+use crate::output::Format;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// --config を指定しなかった場合にカレントディレクトリから探すファイル
+const DEFAULT_CONFIG_FILES: [&str; 3] = ["check404.toml", "check404.yaml", "check404.yml"];
+
+// --profile を指定しなかった場合に使うプロファイル
+const DEFAULT_PROFILE: &str = "default";
+
+// 設定ファイル全体。プロファイル名ごとにクロールの設定を持つ
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub start_urls: Option<Vec<String>>,
+    pub depth: Option<u32>,
+    pub depth_mode: Option<DepthMode>,
+    pub normalize: Option<Vec<NormalizeStep>>,
+    pub no_normalize: Option<Vec<NormalizeStep>>,
+    // 相対パスは設定ファイルのあるディレクトリからのパスとして扱う
+    pub patterns: Option<PathBuf>,
+    pub pattern_limit: Option<usize>,
+    #[serde(default)]
+    pub scope: ScopeProfile,
+    #[serde(default)]
+    pub http: HttpProfile,
+    #[serde(default)]
+    pub output: OutputProfile,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ScopeProfile {
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    pub check_external: Option<bool>,
+    pub ignore_robots: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct HttpProfile {
+    pub concurrency: Option<u32>,
+    pub rate: Option<f64>,
+    pub delay: Option<u64>,
+    pub max_redirects: Option<usize>,
+    pub retries: Option<u32>,
+    pub retry_delay: Option<u64>,
+    pub retry_jitter: Option<bool>,
+    pub connect_timeout: Option<f64>,
+    pub read_timeout: Option<f64>,
+    pub timeout: Option<f64>,
+    pub user_agent: Option<String>,
+    pub pool_idle_timeout: Option<f64>,
+    pub pool_max_idle: Option<usize>,
+}
+
+// file, html_report, db の相対パスも設定ファイルのあるディレクトリからのパスとして扱う
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct OutputProfile {
+    pub file: Option<PathBuf>,
+    pub format: Option<Format>,
+    pub html_report: Option<PathBuf>,
+    pub fail_on: Option<String>,
+    pub db: Option<PathBuf>,
+}
+
+impl ConfigFile {
+    // 拡張子が .yaml/.yml なら YAML、それ以外は TOML として読み込む
+    pub fn load(path: &Path) -> Result<ConfigFile, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let is_yaml = matches!(path.extension().and_then(|ext| ext.to_str()), Some("yaml" | "yml"));
+        let parsed = if is_yaml {
+            serde_yaml::from_str(&text).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(&text).map_err(|e| e.to_string())
+        };
+        parsed.map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+// 設定ファイルのプロファイルを args に反映する
+// is_explicit はコマンドラインで指定された引数かどうかを返し、指定されたものは上書きしない
+pub fn apply(args: &mut CrawlArgs, is_explicit: &dyn Fn(&str) -> bool) -> Result<(), String> {
+    let path = match &args.config {
+        Some(path) => path.clone(),
+        None => match DEFAULT_CONFIG_FILES.iter().map(PathBuf::from).find(|path| path.is_file()) {
+            Some(path) => path,
+            None if args.profile.is_some() => return Err("--profile was given but no config file was found".to_string()),
+            None => return Ok(()),
+        },
+    };
+
+    let mut config = ConfigFile::load(&path)?;
+    let name = args.profile.clone().unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+    let Some(profile) = config.profiles.remove(&name) else {
+        if args.profile.is_some() {
+            return Err(format!("{}: no profile named {}", path.display(), name));
+        }
+        return Ok(());
+    };
+
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    profile.apply(args, is_explicit, base_dir).map_err(|e| format!("{}: profile {}: {}", path.display(), name, e))
+}
+
+impl Profile {
+    fn apply(self, args: &mut CrawlArgs, is_explicit: &dyn Fn(&str) -> bool, base_dir: &Path) -> Result<(), String> {
+        let set = |id: &str| !is_explicit(id);
+
+        if let Some(urls) = self.start_urls.filter(|_| set("start_urls")) {
+            args.start_urls = urls.iter().map(|url| cli::parse_url(url)).collect::<Result<_, _>>()?;
+        }
+        if let Some(depth) = self.depth.filter(|_| set("depth")) {
+            args.depth = depth;
+        }
+        if let Some(depth_mode) = self.depth_mode.filter(|_| set("depth_mode")) {
+            args.depth_mode = depth_mode;
+        }
+        if let Some(normalize) = self.normalize.filter(|_| set("normalize")) {
+            args.normalize = normalize;
+        }
+        if let Some(no_normalize) = self.no_normalize.filter(|_| set("no_normalize")) {
+            args.no_normalize = no_normalize;
+        }
+        if let Some(patterns) = self.patterns.filter(|_| set("patterns")) {
+            args.patterns = Some(base_dir.join(patterns));
+        }
+        if let Some(pattern_limit) = self.pattern_limit.filter(|_| set("pattern_limit")) {
+            args.pattern_limit = pattern_limit;
+        }
+
+        let scope = self.scope;
+        if let Some(include) = scope.include.filter(|_| set("include")) {
+            args.include = compile_all(&include)?;
+        }
+        if let Some(exclude) = scope.exclude.filter(|_| set("exclude")) {
+            args.exclude = compile_all(&exclude)?;
+        }
+        // --no-check-external などの打ち消すフラグも、コマンドラインで指定したものとして扱う
+        if let Some(check_external) = scope.check_external.filter(|_| set("check_external") && !args.no_check_external) {
+            args.check_external = check_external;
+        }
+        if let Some(ignore_robots) = scope.ignore_robots.filter(|_| set("ignore_robots") && !args.respect_robots) {
+            args.ignore_robots = ignore_robots;
+        }
+
+        let http = self.http;
+        if let Some(concurrency) = http.concurrency.filter(|_| set("concurrency")) {
+            if concurrency == 0 {
+                return Err("concurrency must be at least 1".to_string());
+            }
+            args.concurrency = concurrency;
+        }
+        if let Some(rate) = http.rate.filter(|_| set("rate")) {
+            args.rate = Some(positive("rate", rate)?);
+        }
+        if let Some(delay) = http.delay.filter(|_| set("delay")) {
+            args.delay = delay;
+        }
+        if let Some(max_redirects) = http.max_redirects.filter(|_| set("max_redirects")) {
+            args.max_redirects = max_redirects;
+        }
+        if let Some(retries) = http.retries.filter(|_| set("retries")) {
+            args.retries = retries;
+        }
+        if let Some(retry_delay) = http.retry_delay.filter(|_| set("retry_delay")) {
+            args.retry_delay = retry_delay;
+        }
+        if let Some(retry_jitter) = http.retry_jitter.filter(|_| set("no_retry_jitter") && !args.retry_jitter) {
+            args.no_retry_jitter = !retry_jitter;
+        }
+        if let Some(secs) = http.connect_timeout.filter(|_| set("connect_timeout")) {
+            args.connect_timeout = positive("connect_timeout", secs)?;
+        }
+        if let Some(secs) = http.read_timeout.filter(|_| set("read_timeout")) {
+            args.read_timeout = positive("read_timeout", secs)?;
+        }
+        if let Some(secs) = http.timeout.filter(|_| set("timeout")) {
+            args.timeout = positive("timeout", secs)?;
+        }
+        if let Some(user_agent) = http.user_agent.filter(|_| set("user_agent")) {
+            args.user_agent = Some(user_agent);
+        }
+        if let Some(secs) = http.pool_idle_timeout.filter(|_| set("pool_idle_timeout")) {
+            args.pool_idle_timeout = positive("pool_idle_timeout", secs)?;
+        }
+        if let Some(max) = http.pool_max_idle.filter(|_| set("pool_max_idle")) {
+            args.pool_max_idle = max;
+        }
+
+        let output = self.output;
+        if let Some(file) = output.file.filter(|_| set("output")) {
+            args.output = Some(base_dir.join(file));
+        }
+        if let Some(format) = output.format.filter(|_| set("format")) {
+            args.format = format;
+        }
+        if let Some(html_report) = output.html_report.filter(|_| set("html_report")) {
+            args.html_report = Some(base_dir.join(html_report));
+        }
+        if let Some(fail_on) = output.fail_on.filter(|_| set("fail_on")) {
+            args.fail_on = FailOn::parse(&fail_on)?;
+        }
+        if let Some(db) = output.db.filter(|_| set("db")) {
+            args.db = base_dir.join(db);
+        }
+        Ok(())
+    }
+}
+
+fn compile_all(patterns: &[String]) -> Result<Vec<Regex>, String> {
+    patterns.iter()
+        .map(|pattern| Regex::new(pattern).map_err(|e| e.to_string()))
+        .collect()
+}
+
+fn positive(name: &str, value: f64) -> Result<f64, String> {
+    if value > 0.0 && value.is_finite() {
+        Ok(value)
+    } else {
+        Err(format!("{} must be a positive number, got {}", name, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Command};
+    use std::io::Write;
+
+    fn crawl_args(args: &[&str]) -> CrawlArgs {
+        match Cli::try_parse_args(args).unwrap().command {
+            Command::Crawl(args) => *args,
+            command => panic!("unexpected command: {:?}", command),
+        }
+    }
+
+    fn write_config(suffix: &str, text: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::Builder::new().suffix(suffix).tempfile().unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+        file
+    }
+
+    const TOML_CONFIG: &str = r#"
+[profiles.default]
+depth = 1
+
+[profiles.docs]
+start_urls = ["https://example.com/docs/", "https://example.org/"]
+depth = 5
+depth_mode = "path"
+patterns = "docs-patterns.txt"
+pattern_limit = 10
+
+[profiles.docs.scope]
+exclude = ['\.pdf$']
+check_external = true
+
+[profiles.docs.http]
+concurrency = 8
+timeout = 15
+retry_jitter = false
+
+[profiles.docs.output]
+file = "docs-report.txt"
+format = "ndjson"
+fail_on = "404,5xx"
+"#;
+
+    #[test]
+    fn test_toml_profile() {
+        let config = write_config(".toml", TOML_CONFIG);
+        let path = config.path().to_str().unwrap();
+
+        let mut args = crawl_args(&["check404", "--config", path, "--profile", "docs"]);
+        apply(&mut args, &|_| false).unwrap();
+        let urls: Vec<&str> = args.start_urls.iter().map(|url| url.as_str()).collect();
+        assert_eq!(urls, vec!["https://example.com/docs/", "https://example.org/"]);
+        assert_eq!(args.depth, 5);
+        assert_eq!(args.depth_mode, DepthMode::Path);
+        assert_eq!(args.patterns, Some(config.path().parent().unwrap().join("docs-patterns.txt")));
+        assert_eq!(args.pattern_limit, 10);
+        assert_eq!(args.exclude.len(), 1);
+        assert!(args.check_external);
+        assert_eq!(args.concurrency, 8);
+        assert_eq!(args.timeout, 15.0);
+        assert!(args.no_retry_jitter);
+        assert_eq!(args.output, Some(config.path().parent().unwrap().join("docs-report.txt")));
+        assert_eq!(args.format, Format::Ndjson);
+        assert_eq!(args.fail_on, FailOn::parse("404,5xx").unwrap());
+
+        // --profile を省略すると default プロファイルを使う
+        let mut args = crawl_args(&["check404", "--config", path]);
+        apply(&mut args, &|_| false).unwrap();
+        assert_eq!(args.depth, 1);
+    }
+
+    #[test]
+    fn test_cli_flags_override_profile() {
+        let config = write_config(".toml", TOML_CONFIG);
+        let path = config.path().to_str().unwrap();
+
+        let mut args = crawl_args(&["check404", "http://localhost:8080/", "--config", path, "--profile", "docs", "-d=2"]);
+        apply(&mut args, &|id| id == "depth" || id == "start_urls").unwrap();
+        assert_eq!(args.depth, 2);
+        assert_eq!(args.start_urls, vec![url::Url::parse("http://localhost:8080/").unwrap()]);
+        assert_eq!(args.concurrency, 8);
+    }
+
+    #[test]
+    fn test_negating_flags_override_profile() {
+        let config = write_config(".toml", "[profiles.default.scope]\ncheck_external = true\nignore_robots = true\n\n[profiles.default.http]\nretry_jitter = false\n");
+        let path = config.path().to_str().unwrap();
+
+        let mut args = crawl_args(&["check404", "--config", path, "--no-check-external", "--respect-robots", "--retry-jitter"]);
+        apply(&mut args, &|_| false).unwrap();
+        assert!(!args.check_external);
+        assert!(!args.ignore_robots);
+        assert!(!args.no_retry_jitter);
+
+        // 後に指定したフラグが優先される
+        let args = crawl_args(&["check404", "--no-check-external", "--check-external", "--ignore-robots", "--respect-robots"]);
+        assert!(args.check_external);
+        assert!(!args.ignore_robots);
+    }
+
+    #[test]
+    fn test_output_paths_relative_to_config() {
+        let config = write_config(".toml", "[profiles.default.output]\nfile = \"out/report.txt\"\nhtml_report = \"report.html\"\ndb = \"/var/lib/check404/crawl.db\"\n");
+        let base_dir = config.path().parent().unwrap();
+        let mut args = crawl_args(&["check404", "--config", config.path().to_str().unwrap()]);
+        apply(&mut args, &|_| false).unwrap();
+        assert_eq!(args.output, Some(base_dir.join("out/report.txt")));
+        assert_eq!(args.html_report, Some(base_dir.join("report.html")));
+        // 絶対パスはそのまま
+        assert_eq!(args.db, PathBuf::from("/var/lib/check404/crawl.db"));
+    }
+
+    #[test]
+    fn test_yaml_profile() {
+        let config = write_config(".yaml", "profiles:\n  nightly:\n    depth: 7\n    http:\n      rate: 2.5\n      user_agent: nightly-bot\n");
+        let mut args = crawl_args(&["check404", "--config", config.path().to_str().unwrap(), "--profile", "nightly"]);
+        apply(&mut args, &|_| false).unwrap();
+        assert_eq!(args.depth, 7);
+        assert_eq!(args.rate, Some(2.5));
+        assert_eq!(args.user_agent.as_deref(), Some("nightly-bot"));
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let config = write_config(".toml", TOML_CONFIG);
+        let path = config.path().to_str().unwrap();
+        let mut args = crawl_args(&["check404", "--config", path, "--profile", "missing"]);
+        assert!(apply(&mut args, &|_| false).is_err());
+
+        // 設定項目の綴り間違いはエラーにする
+        let config = write_config(".toml", "[profiles.default]\ndepht = 3\n");
+        let mut args = crawl_args(&["check404", "--config", config.path().to_str().unwrap()]);
+        assert!(apply(&mut args, &|_| false).is_err());
+
+        let config = write_config(".toml", "[profiles.default.http]\nconcurrency = 0\n");
+        let mut args = crawl_args(&["check404", "--config", config.path().to_str().unwrap()]);
+        assert!(apply(&mut args, &|_| false).is_err());
+    }
+}
@@ -0,0 +1,1190 @@
+use crate::fetch::{self, RedirectHop, RedirectIssue};
+use crate::findings::{self, ErrorCategory, Finding};
+use crate::normalize::Normalizer;
+use crate::patterns::UrlPatterns;
+use crate::rate_limit::RateLimiter;
+use crate::retry::RetryPolicy;
+use crate::robots::Robots;
+use crate::db::{FrontierKind, FrontierRecord};
+use crate::storage::{ResumedRun, Storage};
+use crate::{db, BoxError};
+use regex::Regex;
+use reqwest::Method;
+use scraper::{Html, Selector};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use url::Url;
+
+// キューに入ったURL。external はサイト外のリンクで、ステータス確認のみ行う
+#[derive(Debug, PartialEq)]
+struct QueuedUrl {
+    url: String,
+    external: bool,
+    // 開始URLから辿ったリンクの数
+    hops: usize,
+}
+
+// -d の深さの数え方
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DepthMode {
+    // 開始URLから何回リンクを辿ったか
+    #[default]
+    Hops,
+    // URLのパスのセグメントの数（以前の動作）
+    // 階層ごとに待たないので、記録する hops は最短とは限らない（最初に見つかったリンクでの数）
+    Path,
+}
+
+// ページ内で見つかったリンク
+#[derive(Clone, Debug, PartialEq)]
+struct Link {
+    url: String,
+    href: String,
+    anchor_text: String,
+}
+
+// リンク元のページとリンクの記述
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Referrer {
+    pub source_url: String,
+    pub href: String,
+    pub anchor_text: String,
+}
+
+// 問題のあったリダイレクトチェーン
+#[derive(Clone, Debug, PartialEq)]
+pub struct RedirectReport {
+    pub url: String,
+    pub hops: Vec<RedirectHop>,
+    pub issues: Vec<RedirectIssue>,
+}
+
+// パターンの上限で辿らなかったURLの集計（--patterns のグループごと）
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PatternReport {
+    pub pattern: String,
+    // キューに入れたURLの数
+    pub crawled: usize,
+    // 上限を超えて辿らなかったURLの数
+    pub suppressed: usize,
+}
+
+// チェックしたURL1件分の結果（--format json/ndjson の出力に使う）
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckResult {
+    pub url: String,
+    pub status: Option<u16>,
+    pub external: bool,
+    // パスの深さ。サイト外のリンクは None
+    pub depth: Option<usize>,
+    // 開始URLから辿ったリンクの数（hops モードでは最短の数。path モードでは最初に見つかったリンクでの数）
+    pub hops: usize,
+    pub elapsed: Duration,
+    pub attempts: u32,
+    pub redirect_count: usize,
+    pub error: Option<Finding>,
+}
+
+// クロールの動作を決めるオプション
+pub struct CrawlOptions {
+    pub max_depth: u32,
+    pub depth_mode: DepthMode,
+    pub normalizer: Normalizer,
+    pub check_external: bool,
+    pub max_redirects: usize,
+    pub retry: RetryPolicy,
+    // 空でなければ、いずれかに一致するサイト内のURLだけを辿る
+    pub include: Vec<Regex>,
+    // 一致するURLは辿らず、サイト外のリンクでもステータスを確認しない
+    pub exclude: Vec<Regex>,
+    // 進捗を標準エラー出力に出す（標準出力を JSON などの結果だけにするため）
+    pub progress_to_stderr: bool,
+    // 保存する結果に付ける runs.id
+    pub run_id: i64,
+}
+
+// ワーカー間で共有するクロールのフロンティア
+struct Frontier {
+    queue: VecDeque<QueuedUrl>,
+    visited: HashSet<String>,
+    // パターンのグループごとにキューに入れたURLの数
+    pattern_counts: HashMap<String, usize>,
+    // 上限を超えたため辿らなかったURL（グループごと、重複なし）
+    suppressed: HashMap<String, HashSet<String>>,
+    skipped: Vec<String>,
+    external_count: usize,
+    referrers: HashMap<String, Vec<Referrer>>,
+    findings: Vec<Finding>,
+    results: Vec<CheckResult>,
+    redirects: Vec<RedirectReport>,
+    in_flight: usize,
+    // 処理中のURLのホップ数。hops モードでは幅優先で辿るため、この階層が全て終わるまで次の階層は取り出さない
+    level: usize,
+    // 中断の要求を受けたら true。キューに残ったURLは取り出さない
+    stopped: bool,
+}
+
+pub struct Crawler {
+    frontier: Mutex<Frontier>,
+    notify: Notify,
+    options: CrawlOptions,
+    base_urls: Vec<Url>,
+    client: reqwest::Client,
+    patterns: UrlPatterns,
+    storage: Mutex<Box<dyn Storage>>,
+    rate_limiter: RateLimiter,
+    robots: HashMap<String, Robots>,
+}
+
+impl Crawler {
+    // robots は開始URLのホスト名ごとの robots.txt（無視する場合は空）
+    pub fn new(options: CrawlOptions, base_urls: Vec<Url>, client: reqwest::Client, patterns: UrlPatterns, storage: Box<dyn Storage>, rate_limiter: RateLimiter, robots: HashMap<String, Robots>) -> Self {
+        Crawler {
+            frontier: Mutex::new(Frontier {
+                queue: VecDeque::new(),
+                visited: HashSet::new(),
+                pattern_counts: HashMap::new(),
+                suppressed: HashMap::new(),
+                skipped: Vec::new(),
+                external_count: 0,
+                referrers: HashMap::new(),
+                findings: Vec::new(),
+                results: Vec::new(),
+                redirects: Vec::new(),
+                in_flight: 0,
+                level: 0,
+                stopped: false,
+            }),
+            notify: Notify::new(),
+            options,
+            base_urls,
+            client,
+            patterns,
+            storage: Mutex::new(storage),
+            rate_limiter,
+            robots,
+        }
+    }
+
+    // 開始URLをフロンティアに追加する（深さとパターンの制限は受けない）
+    pub fn seed(&self, start_url: &str) -> Result<(), BoxError> {
+        let url_without_hash = start_url.split('#').next().unwrap_or(start_url);
+        let mut frontier = self.frontier.lock().unwrap();
+        if !frontier.visited.insert(self.normalize(url_without_hash)) {
+            return Ok(());
+        }
+        let kind = if self.check_robots(&mut frontier, url_without_hash) {
+            frontier.queue.push_back(QueuedUrl { url: url_without_hash.to_string(), external: false, hops: 0 });
+            FrontierKind::Internal
+        } else {
+            FrontierKind::Robots
+        };
+        self.save_frontier(vec![frontier_record(url_without_hash, kind, 0)])
+    }
+
+    // --resume で保存済みのフロンティアを戻す。辿り終えていないURLだけをキューに入れる
+    // 辿り終えたURLの結果・リンク元・リダイレクトも戻し、集計と終了コードは実行全体から求める
+    // （辿り終えていないURLはもう一度チェックするので、その結果は戻さない）
+    pub fn restore(&self, resumed: ResumedRun) {
+        let done: HashSet<String> = resumed.frontier.iter().filter(|record| record.done).map(|record| record.url.clone()).collect();
+        let mut frontier = self.frontier.lock().unwrap();
+        for record in resumed.frontier {
+            let normalized_url_str = self.normalize(&record.url);
+            if record.kind == FrontierKind::Suppressed {
+                if let Some((group, _)) = self.patterns.group(&normalized_url_str) {
+                    frontier.suppressed.entry(group).or_default().insert(normalized_url_str);
+                }
+                continue;
+            }
+            frontier.visited.insert(normalized_url_str.clone());
+            match record.kind {
+                // 開始URL（hops が 0）は seed と同じくパターンの上限に数えない
+                FrontierKind::Internal if record.hops > 0 => {
+                    if let Some((group, _)) = self.patterns.group(&normalized_url_str) {
+                        *frontier.pattern_counts.entry(group).or_insert(0) += 1;
+                    }
+                }
+                FrontierKind::Internal | FrontierKind::Suppressed => {}
+                FrontierKind::External => frontier.external_count += 1,
+                FrontierKind::Robots => {
+                    frontier.skipped.push(record.url);
+                    continue;
+                }
+            }
+            if !record.done {
+                frontier.queue.push_back(QueuedUrl { url: record.url, external: record.kind == FrontierKind::External, hops: record.hops });
+            }
+        }
+
+        for (source_url, link) in resumed.links.into_iter().filter(|(source_url, _)| done.contains(source_url)) {
+            let referrer = Referrer { source_url, href: link.href, anchor_text: link.anchor_text };
+            let referrers = frontier.referrers.entry(self.normalize(&link.target_url)).or_default();
+            if !referrers.contains(&referrer) {
+                referrers.push(referrer);
+            }
+        }
+
+        // 最後まで辿らなかったリダイレクトは、リダイレクトのエラーとして保存している
+        let final_statuses: HashMap<&str, Option<u16>> = resumed.pages.iter()
+            .map(|page| (page.check_url.as_str(), Some(page.status).filter(|_| page.error_category != Some(ErrorCategory::Redirect.as_str()))))
+            .collect();
+        for (check_url, hops) in resumed.redirects.iter().filter(|(check_url, _)| done.contains(check_url)) {
+            let hops: Vec<RedirectHop> = hops.iter().map(|hop| RedirectHop { url: hop.url.clone(), status: hop.status, location: hop.location.clone() }).collect();
+            let final_status = final_statuses.get(check_url.as_str()).copied().flatten();
+            let issues = fetch::stored_redirect_issues(&hops, final_status, self.options.max_redirects, |next| self.robots_allows(next));
+            if !issues.is_empty() {
+                frontier.redirects.push(RedirectReport { url: check_url.clone(), hops, issues });
+            }
+        }
+
+        for page in resumed.pages.into_iter().filter(|page| done.contains(&page.check_url)) {
+            let status = Some(page.status).filter(|status| *status != 0);
+            let error = page.error_category.and_then(ErrorCategory::parse).map(|category| Finding {
+                url: page.check_url.clone(),
+                status,
+                category,
+                message: page.error_message,
+            });
+            frontier.findings.extend(error.clone());
+            frontier.results.push(CheckResult {
+                depth: (!page.external).then(|| path_depth(&self.normalize(&page.check_url))),
+                url: page.check_url,
+                status,
+                external: page.external,
+                hops: page.hops,
+                elapsed: Duration::from_millis(page.elapsed_ms),
+                attempts: page.attempts,
+                redirect_count: page.redirect_count,
+                error,
+            });
+        }
+    }
+
+    // フロンティアの保存はフロンティアのロック中に行い、保存する順番とキューに入れる順番を揃える
+    fn save_frontier(&self, records: Vec<FrontierRecord>) -> Result<(), BoxError> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        self.storage.lock().unwrap().insert_frontier(self.options.run_id, records)
+    }
+
+    // robots.txt で拒否されたURLは skipped に記録して false を返す
+    fn check_robots(&self, frontier: &mut Frontier, url: &str) -> bool {
+        if Url::parse(url).is_ok_and(|parsed| !self.robots_allows(&parsed)) {
+            self.log(format_args!("skipped: robots {}", url));
+            frontier.skipped.push(url.to_string());
+            return false;
+        }
+        true
+    }
+
+    // robots.txt を読み込んだホスト以外は全て許可する
+    fn robots_allows(&self, url: &Url) -> bool {
+        url.host_str().and_then(|host| self.robots.get(host)).is_none_or(|robots| robots.is_allowed(url))
+    }
+
+    // visited とリンク元の対応付けに使うURL
+    fn normalize(&self, url: &str) -> String {
+        self.options.normalizer.normalize(url)
+    }
+
+    // チェックを終えたURLの数（サイト内, サイト外）。中断した場合、キューに残ったURLは数えない
+    pub fn checked_counts(&self) -> (usize, usize) {
+        let frontier = self.frontier.lock().unwrap();
+        let external = frontier.results.iter().filter(|result| result.external).count();
+        (frontier.results.len() - external, external)
+    }
+
+    // リダイレクトで辿り着いたURLも、取得済みとして visited に入れる
+    fn mark_visited(&self, url: &str) {
+        let normalized_url_str = self.normalize(url);
+        self.frontier.lock().unwrap().visited.insert(normalized_url_str);
+    }
+
+    fn log(&self, message: impl Display) {
+        if self.options.progress_to_stderr {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
+
+    fn record_finding(&self, finding: Finding) {
+        self.frontier.lock().unwrap().findings.push(finding);
+    }
+
+    fn record_check(&self, result: CheckResult) {
+        self.frontier.lock().unwrap().results.push(result);
+    }
+
+    // チェックした全てのURLの結果（チェックした順）
+    pub fn results(&self) -> Vec<CheckResult> {
+        self.frontier.lock().unwrap().results.clone()
+    }
+
+    fn record_redirect(&self, report: RedirectReport) {
+        self.frontier.lock().unwrap().redirects.push(report);
+    }
+
+    pub fn redirect_report(&self) -> Vec<RedirectReport> {
+        self.frontier.lock().unwrap().redirects.clone()
+    }
+
+    pub fn referrers(&self, url: &str) -> Vec<Referrer> {
+        self.frontier.lock().unwrap().referrers.get(&self.normalize(url)).cloned().unwrap_or_default()
+    }
+
+    // 問題のあったURLとそのリンク元の一覧
+    pub fn findings_report(&self) -> Vec<(Finding, Vec<Referrer>)> {
+        let frontier = self.frontier.lock().unwrap();
+        frontier.findings.iter()
+            .map(|finding| (finding.clone(), frontier.referrers.get(&self.normalize(&finding.url)).cloned().unwrap_or_default()))
+            .collect()
+    }
+
+    // パターンの上限で辿らなかったURLの数（グループごと、多い順）
+    pub fn pattern_report(&self) -> Vec<PatternReport> {
+        let frontier = self.frontier.lock().unwrap();
+        let mut report: Vec<PatternReport> = frontier.suppressed.iter()
+            .map(|(group, urls)| PatternReport {
+                pattern: group.clone(),
+                crawled: frontier.pattern_counts.get(group).copied().unwrap_or(0),
+                suppressed: urls.len(),
+            })
+            .collect();
+        report.sort_by(|a, b| b.suppressed.cmp(&a.suppressed).then_with(|| a.pattern.cmp(&b.pattern)));
+        report
+    }
+
+    pub fn skipped_count(&self) -> usize {
+        self.frontier.lock().unwrap().skipped.len()
+    }
+
+    // robots.txt で拒否したURL（見つけた順）
+    pub fn skipped_urls(&self) -> Vec<String> {
+        self.frontier.lock().unwrap().skipped.clone()
+    }
+
+    pub fn run_id(&self) -> i64 {
+        self.options.run_id
+    }
+
+    // runs の行に終了時刻と集計を書き込む
+    pub fn finish_run(&self, totals: &db::RunTotals, exit_status: &str) -> Result<(), BoxError> {
+        self.storage.lock().unwrap().finish_run(self.options.run_id, totals, exit_status)
+    }
+
+    // 新しいURLの取り出しをやめる。処理中のURLはそのまま終わらせ、キューに残ったURLはフロンティアに
+    // 保存済みなので --resume で続きから実行できる
+    pub fn stop(&self) {
+        self.frontier.lock().unwrap().stopped = true;
+        self.notify.notify_waiters();
+    }
+
+    pub fn interrupted(&self) -> bool {
+        self.frontier.lock().unwrap().stopped
+    }
+
+    pub fn throttle_stats(&self) -> (u64, Duration) {
+        self.rate_limiter.throttle_stats()
+    }
+
+    // 一時的な失敗は再試行する。最後の結果と試行回数を返す
+    // read_body なら成功したレスポンスの本文も読み、本文の途中でのタイムアウトや切断も再試行する
+    async fn fetch_with_retry(&self, method: Method, url: &str, read_body: bool) -> Result<(Result<fetch::Fetched, reqwest::Error>, u32), BoxError> {
+        let parsed_url = Url::parse(url)?;
+        let policy = &self.options.retry;
+        let mut attempt = 1;
+        loop {
+            let mut result = fetch::fetch(&self.client, method.clone(), &parsed_url, &self.rate_limiter, |next| self.robots_allows(next)).await;
+            if let Ok(fetched) = &mut result {
+                let succeeded = fetched.response.as_ref().is_some_and(|response| ErrorCategory::from_status(response.status().as_u16()).is_none());
+                if read_body && succeeded && !fetched.disallowed {
+                    fetched.read_body().await;
+                }
+            }
+
+            let delay = match &result {
+                Ok(fetched) => match (&fetched.response, &fetched.body) {
+                    (_, Some(Err(e))) if policy.should_retry_error(e) => Some(policy.delay(attempt, None)),
+                    // HEAD に対応していないサーバーは、再試行せずにすぐ GET で確認する
+                    (Some(response), _) if method == Method::HEAD && matches!(response.status().as_u16(), 405 | 501) => None,
+                    (Some(response), _) if policy.should_retry_status(response.status().as_u16()) => Some(policy.delay(attempt, Some(response.headers()))),
+                    _ => None,
+                },
+                Err(e) if policy.should_retry_error(e) => Some(policy.delay(attempt, None)),
+                Err(_) => None,
+            };
+
+            match delay {
+                Some(delay) if attempt < policy.max_attempts => {
+                    self.log(format_args!("Retrying: {} in {:.1}秒 (attempt {}/{})", url, delay.as_secs_f64(), attempt + 1, policy.max_attempts));
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                _ => return Ok((result, attempt)),
+            }
+        }
+    }
+
+    fn in_scope(&self, url: &str, internal: bool) -> bool {
+        if self.options.exclude.iter().any(|pattern| pattern.is_match(url)) {
+            return false;
+        }
+        !internal || self.options.include.is_empty() || self.options.include.iter().any(|pattern| pattern.is_match(url))
+    }
+
+    fn is_internal(&self, url: &str) -> bool {
+        Url::parse(url).is_ok_and(|url| self.base_urls.iter().any(|base_url| url.domain() == base_url.domain()))
+    }
+
+    // キューからURLを取り出す。全ワーカーが待機状態でキューが空か、中断した場合はNoneを返す
+    async fn next_url(&self) -> Option<QueuedUrl> {
+        loop {
+            let notified = self.notify.notified();
+            {
+                let mut frontier = self.frontier.lock().unwrap();
+                if frontier.stopped {
+                    return None;
+                }
+                // 階層ごとに待つのは hops モードだけ（path モードの深さは辿った順に関係しない）
+                let level_done = |next: &QueuedUrl| frontier.in_flight == 0 || self.options.depth_mode == DepthMode::Path || next.hops <= frontier.level;
+                match frontier.queue.front() {
+                    Some(next) if level_done(next) => {
+                        frontier.level = next.hops;
+                        frontier.in_flight += 1;
+                        return frontier.queue.pop_front();
+                    }
+                    // 前の階層のページから、より近いリンクが見つかるかもしれないので待つ
+                    Some(_) => {}
+                    None if frontier.in_flight == 0 => return None,
+                    None => {}
+                }
+            }
+            notified.await;
+        }
+    }
+
+    // 処理を終えたURLから見つかったリンクをフロンティアに追加する
+    // 追加したURLを保存してから、処理を終えたURLを辿り終えたものとして保存する
+    fn complete(&self, source: &QueuedUrl, links: Vec<Link>) -> Result<(), BoxError> {
+        let source_url = source.url.as_str();
+        let hops = source.hops + 1;
+        let mut frontier = self.frontier.lock().unwrap();
+        let mut records = Vec::new();
+        for link in links {
+            let normalized_url_str = self.normalize(&link.url);
+
+            // リンク元は辿るかどうかに関わらず全て記録する
+            let referrer = Referrer { source_url: source_url.to_string(), href: link.href, anchor_text: link.anchor_text };
+            let referrers = frontier.referrers.entry(normalized_url_str.clone()).or_default();
+            if !referrers.contains(&referrer) {
+                referrers.push(referrer);
+            }
+
+            let url_str = link.url;
+            let internal = self.is_internal(&url_str);
+            if !self.in_scope(&url_str, internal) {
+                continue;
+            }
+
+            // サイト外のリンクは一度だけステータスを確認し、その先は辿らない
+            if !internal {
+                if self.options.check_external && frontier.visited.insert(normalized_url_str) {
+                    frontier.external_count += 1;
+                    records.push(frontier_record(&url_str, FrontierKind::External, hops));
+                    frontier.queue.push_back(QueuedUrl { url: url_str, external: true, hops });
+                }
+                continue;
+            }
+
+            let depth = match self.options.depth_mode {
+                DepthMode::Hops => hops,
+                DepthMode::Path => path_depth(&normalized_url_str),
+            };
+            if depth <= self.options.max_depth as usize && !frontier.visited.contains(&normalized_url_str) {
+                if !self.check_robots(&mut frontier, &url_str) {
+                    frontier.visited.insert(normalized_url_str);
+                    records.push(frontier_record(&url_str, FrontierKind::Robots, hops));
+                    continue;
+                }
+                if let Some((group, limit)) = self.patterns.group(&normalized_url_str) {
+                    let count = frontier.pattern_counts.entry(group.clone()).or_insert(0);
+                    if *count >= limit {
+                        if frontier.suppressed.entry(group).or_default().insert(normalized_url_str) {
+                            records.push(frontier_record(&url_str, FrontierKind::Suppressed, hops));
+                        }
+                        continue;
+                    }
+                    *count += 1;
+                }
+                frontier.visited.insert(normalized_url_str);
+                records.push(frontier_record(&url_str, FrontierKind::Internal, hops));
+                frontier.queue.push_back(QueuedUrl { url: url_str, external: false, hops });
+            }
+        }
+        let saved = self.save_frontier(records).and_then(|_| self.storage.lock().unwrap().mark_done(self.options.run_id, source_url));
+        frontier.in_flight -= 1;
+        drop(frontier);
+        self.notify.notify_waiters();
+        saved
+    }
+
+    // エラーで処理を終えられなかったURL（辿り終えたものとしては保存しない）
+    fn abandon(&self) {
+        self.frontier.lock().unwrap().in_flight -= 1;
+        self.notify.notify_waiters();
+    }
+}
+
+pub async fn crawl(crawler: Arc<Crawler>) -> Result<(), BoxError> {
+    while let Some(queued) = crawler.next_url().await {
+        let result = if queued.external {
+            check_external(&crawler, &queued).await.map(|_| Vec::new())
+        } else {
+            check_page(&crawler, &queued).await
+        };
+        match result {
+            Ok(links) => crawler.complete(&queued, links)?,
+            Err(e) => {
+                crawler.abandon();
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// チェック中のURLと、結果と一緒に保存する情報
+struct CheckTarget<'a> {
+    url: &'a str,
+    domain: &'a str,
+    external: bool,
+    attempts: u32,
+    redirect_count: usize,
+    hops: usize,
+    started: Instant,
+}
+
+async fn check_page(crawler: &Crawler, queued: &QueuedUrl) -> Result<Vec<Link>, BoxError> {
+    let url = queued.url.as_str();
+    crawler.log(format_args!("Crawling: {}", url));
+
+    let domain = Url::parse(url)?.domain().unwrap_or("").to_string();
+    let mut target = CheckTarget { url, domain: &domain, external: false, attempts: 0, redirect_count: 0, hops: queued.hops, started: Instant::now() };
+    let (result, attempts) = crawler.fetch_with_retry(Method::GET, url, true).await?;
+    target.attempts = attempts;
+    let mut fetched = match result {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            record_result(crawler, &target, None, Some((ErrorCategory::from_reqwest(&e), findings::error_message(&e))))?;
+            return Ok(Vec::new());
+        }
+    };
+    target.redirect_count = fetched.hops.len();
+    let body = fetched.body.take();
+    let Some(response) = record_redirects(crawler, &target, fetched)? else {
+        return Ok(Vec::new());
+    };
+    let status = response.status().as_u16();
+    let final_url = response.url().to_string();
+
+    if let Some(category) = ErrorCategory::from_status(status) {
+        record_result(crawler, &target, Some(status), Some((category, String::new())))?;
+        return Ok(Vec::new());
+    }
+
+    let html = match body {
+        Some(Ok(html)) => html,
+        None => String::new(),
+        Some(Err(e)) => {
+            record_result(crawler, &target, Some(status), Some((ErrorCategory::from_reqwest(&e), findings::error_message(&e))))?;
+            return Ok(Vec::new());
+        }
+    };
+    record_result(crawler, &target, Some(status), None)?;
+
+    // リダイレクトされた場合、相対リンクは最終的なURLを基準に解決する
+    let links = extract_links(&final_url, &html)?;
+    crawler.storage.lock().unwrap().insert_links(crawler.options.run_id, url, links.iter().map(|link| db::LinkRecord {
+        target_url: crawler.normalize(&link.url),
+        href: link.href.clone(),
+        anchor_text: link.anchor_text.clone(),
+    }).collect())?;
+    Ok(links)
+}
+
+// サイト外のリンクは HEAD で確認し、失敗した場合のみ GET で確認する
+async fn check_external(crawler: &Crawler, queued: &QueuedUrl) -> Result<(), BoxError> {
+    let url = queued.url.as_str();
+    crawler.log(format_args!("Checking external: {}", url));
+
+    let domain = Url::parse(url)?.domain().unwrap_or("").to_string();
+    let mut target = CheckTarget { url, domain: &domain, external: true, attempts: 0, redirect_count: 0, hops: queued.hops, started: Instant::now() };
+    let (mut result, mut attempts) = crawler.fetch_with_retry(Method::HEAD, url, false).await?;
+    let head_ok = result.as_ref().is_ok_and(|fetched| {
+        fetched.response.as_ref().is_some_and(|response| !response.status().is_client_error() && !response.status().is_server_error())
+    });
+    if !head_ok {
+        let (get_result, get_attempts) = crawler.fetch_with_retry(Method::GET, url, false).await?;
+        result = get_result;
+        attempts += get_attempts;
+    }
+    target.attempts = attempts;
+
+    match result {
+        Ok(fetched) => {
+            target.redirect_count = fetched.hops.len();
+            if let Some(response) = record_redirects(crawler, &target, fetched)? {
+                let status = response.status().as_u16();
+                let error = ErrorCategory::from_status(status).map(|category| (category, String::new()));
+                record_result(crawler, &target, Some(status), error)?;
+            }
+            Ok(())
+        }
+        Err(e) => record_result(crawler, &target, None, Some((ErrorCategory::from_reqwest(&e), findings::error_message(&e)))),
+    }
+}
+
+// リダイレクトチェーンを保存し、問題があれば記録する
+// ループや打ち切りで最終レスポンスがない場合と、リダイレクト先が robots.txt で拒否された場合は、エラーとして pages に保存して None を返す
+fn record_redirects(crawler: &Crawler, target: &CheckTarget, fetched: fetch::Fetched) -> Result<Option<reqwest::Response>, BoxError> {
+    if fetched.hops.is_empty() {
+        return Ok(fetched.response);
+    }
+
+    crawler.storage.lock().unwrap().insert_redirects(crawler.options.run_id, target.url, fetched.hops.iter().map(|hop| db::RedirectRecord {
+        url: hop.url.clone(),
+        status: hop.status,
+        location: hop.location.clone(),
+    }).collect())?;
+
+    let issues = fetched.redirect_issues(crawler.options.max_redirects);
+    if !issues.is_empty() {
+        let descriptions: Vec<String> = issues.iter().map(RedirectIssue::describe).collect();
+        crawler.log(format_args!("Redirect Issue: {} ({})", target.url, descriptions.join(", ")));
+        crawler.record_redirect(RedirectReport { url: target.url.to_string(), hops: fetched.hops.clone(), issues: issues.clone() });
+    }
+
+    // robots.txt で拒否されたリダイレクト先は取得していないので、リダイレクトのレスポンスを結果にしない
+    if fetched.response.is_none() || fetched.disallowed {
+        let last_status = fetched.hops.last().map(|hop| hop.status);
+        let message = issues.iter().map(RedirectIssue::describe).collect::<Vec<_>>().join(", ");
+        record_result(crawler, target, last_status, Some((ErrorCategory::Redirect, message)))?;
+        return Ok(None);
+    }
+
+    // チェーンの最後のURLはここで取得したので、そこへの直接のリンクはもう取得しない
+    if let Some(response) = &fetched.response {
+        crawler.mark_visited(response.url().as_str());
+    }
+    Ok(fetched.response)
+}
+
+// チェック結果をストレージに保存し、問題があれば表示して記録する
+fn record_result(crawler: &Crawler, target: &CheckTarget, status: Option<u16>, error: Option<(ErrorCategory, String)>) -> Result<(), BoxError> {
+    let elapsed = target.started.elapsed();
+
+    // 結果を保存
+    crawler.storage.lock().unwrap().insert_page(crawler.options.run_id, db::PageRecord {
+        check_url: target.url.to_string(),
+        normalized_url: crawler.normalize(target.url),
+        domain: target.domain.to_string(),
+        status: status.unwrap_or(0),
+        external: target.external,
+        error_category: error.as_ref().map(|(category, _)| category.as_str()),
+        error_message: error.as_ref().map(|(_, message)| message.clone()).filter(|message| !message.is_empty()),
+        redirect_count: target.redirect_count,
+        attempts: target.attempts,
+        elapsed_ms: elapsed.as_millis() as u64,
+        hops: target.hops,
+    })?;
+
+    let finding = error.map(|(category, message)| Finding {
+        url: target.url.to_string(),
+        status,
+        category,
+        message: Some(message).filter(|message| !message.is_empty()),
+    });
+    if let Some(finding) = &finding {
+        crawler.log(finding.summary());
+        crawler.record_finding(finding.clone());
+    }
+    crawler.record_check(CheckResult {
+        url: target.url.to_string(),
+        status,
+        external: target.external,
+        depth: (!target.external).then(|| path_depth(&crawler.normalize(target.url))),
+        hops: target.hops,
+        elapsed,
+        attempts: target.attempts,
+        redirect_count: target.redirect_count,
+        error: finding,
+    });
+
+    Ok(())
+}
+
+fn extract_links(url: &str, html: &str) -> Result<Vec<Link>, BoxError> {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse("a").unwrap();
+    let page_url = Url::parse(url)?;
+
+    let mut links = Vec::new();
+
+    for element in document.select(&selector) {
+        if let Some(href) = element.value().attr("href") {
+            if !href.starts_with("tel:") && !href.starts_with("mailto:") {
+                if let Ok(mut absolute_url) = page_url.join(href) {
+                    absolute_url.set_fragment(None);
+                    if matches!(absolute_url.scheme(), "http" | "https") {
+                        let anchor_text = element.text().collect::<Vec<_>>().join(" ");
+                        links.push(Link {
+                            url: absolute_url.to_string(),
+                            href: href.to_string(),
+                            anchor_text: anchor_text.split_whitespace().collect::<Vec<_>>().join(" "),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(links)
+}
+
+fn frontier_record(url: &str, kind: FrontierKind, hops: usize) -> FrontierRecord {
+    FrontierRecord { url: url.to_string(), kind, hops, done: matches!(kind, FrontierKind::Robots | FrontierKind::Suppressed) }
+}
+
+// URLのパスの深さ（http://example.com/a/b は 2）。クエリの中のスラッシュは数えない
+fn path_depth(normalized_url: &str) -> usize {
+    Url::parse(normalized_url).ok()
+        .and_then(|url| url.path_segments().map(|segments| segments.filter(|segment| !segment.is_empty()).count()))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::HttpOptions;
+    use crate::patterns::UrlPattern;
+    use crate::storage::{MemoryStorage, Storage};
+
+    // テスト用のクローラー。既定値から変えたい設定だけを指定する
+    struct TestCrawler {
+        start_url: String,
+        options: CrawlOptions,
+        robots: Option<Robots>,
+        patterns: UrlPatterns,
+        storage: MemoryStorage,
+    }
+
+    impl TestCrawler {
+        fn new(start_url: &str) -> TestCrawler {
+            let retry = RetryPolicy { max_attempts: 1, base_delay: Duration::ZERO, jitter: false };
+            let options = CrawlOptions { max_depth: 3, depth_mode: DepthMode::Hops, normalizer: Normalizer::default(), check_external: false, max_redirects: 5, retry, include: Vec::new(), exclude: Vec::new(), progress_to_stderr: false, run_id: 1 };
+            let patterns = UrlPatterns::new(vec![UrlPattern::parse(r"/\d+").unwrap()], 3);
+            TestCrawler { start_url: start_url.to_string(), options, robots: None, patterns, storage: MemoryStorage::default() }
+        }
+
+        fn options(mut self, configure: impl FnOnce(&mut CrawlOptions)) -> TestCrawler {
+            configure(&mut self.options);
+            self
+        }
+
+        fn check_external(self) -> TestCrawler {
+            self.options(|options| options.check_external = true)
+        }
+
+        fn robots(mut self, robots: Robots) -> TestCrawler {
+            self.robots = Some(robots);
+            self
+        }
+
+        fn patterns(mut self, patterns: UrlPatterns) -> TestCrawler {
+            self.patterns = patterns;
+            self
+        }
+
+        fn storage(mut self, storage: &MemoryStorage) -> TestCrawler {
+            self.storage = storage.clone();
+            self
+        }
+
+        // 開始URLをキューに入れたクローラー
+        fn build(self) -> Crawler {
+            let start_url = self.start_url.clone();
+            let crawler = self.build_unseeded();
+            crawler.seed(&start_url).unwrap();
+            crawler
+        }
+
+        // --resume と同じく、restore してから seed するときに使う
+        fn build_unseeded(self) -> Crawler {
+            let robots = self.robots.into_iter().map(|robots| ("example.com".to_string(), robots)).collect();
+            let base_url = Url::parse(&self.start_url).unwrap();
+            let rate_limiter = RateLimiter::new(None, Duration::ZERO);
+            let client = fetch::build_client(&HttpOptions::default()).unwrap();
+            Crawler::new(self.options, vec![base_url], client, self.patterns, Box::new(self.storage), rate_limiter, robots)
+        }
+    }
+
+    // キューに入れたサイト内のURLの数（チェックしたかどうかに関わらない）
+    fn visited_count(crawler: &Crawler) -> usize {
+        let frontier = crawler.frontier.lock().unwrap();
+        frontier.visited.len() - frontier.skipped.len() - frontier.external_count
+    }
+
+    fn external_count(crawler: &Crawler) -> usize {
+        crawler.frontier.lock().unwrap().external_count
+    }
+
+    fn queued(url: &str, hops: usize) -> QueuedUrl {
+        QueuedUrl { url: url.to_string(), external: false, hops }
+    }
+
+    fn links(urls: &[&str]) -> Vec<Link> {
+        urls.iter()
+            .map(|url| Link { url: url.to_string(), href: url.to_string(), anchor_text: String::new() })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_frontier_deduplicates_and_terminates() {
+        let crawler = TestCrawler::new("http://example.com/").build();
+
+        assert_eq!(crawler.next_url().await.map(|queued| queued.url), Some("http://example.com/".to_string()));
+        crawler.complete(&queued("http://example.com/", 0), links(&[
+            "http://example.com/a",
+            "http://EXAMPLE.com/a/",            // 正規化後に重複
+            "http://example.com/a?utm_source=x", // トラッキング用のパラメータだけが違う
+            "http://example.com/index.html",     // 開始URL
+        ])).unwrap();
+
+        assert_eq!(crawler.next_url().await.map(|queued| queued.url), Some("http://example.com/a".to_string()));
+        crawler.complete(&queued("http://example.com/a", 1), Vec::new()).unwrap();
+
+        // キューが空で処理中のURLもなければ終了
+        assert_eq!(crawler.next_url().await, None);
+        assert_eq!(visited_count(&crawler), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stop_leaves_queue() {
+        let crawler = TestCrawler::new("http://example.com/").build();
+        crawler.next_url().await;
+        crawler.stop();
+        // 処理中だったURLの結果は受け付け、見つかったリンクもフロンティアに残す
+        crawler.record_check(CheckResult { url: "http://example.com/".to_string(), status: Some(200), external: false, depth: Some(0), hops: 0, elapsed: Duration::ZERO, attempts: 1, redirect_count: 0, error: None });
+        crawler.complete(&queued("http://example.com/", 0), links(&["http://example.com/a"])).unwrap();
+
+        assert!(crawler.interrupted());
+        assert_eq!(crawler.next_url().await, None);
+        assert_eq!(visited_count(&crawler), 2);
+        // 集計にはチェックしたURLだけを数える
+        assert_eq!(crawler.checked_counts(), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn test_depth_modes() {
+        let crawler = TestCrawler::new("http://example.com/").options(|options| options.max_depth = 1).build();
+        let root = crawler.next_url().await.unwrap();
+        crawler.complete(&root, links(&["http://example.com/a/b/c/d"])).unwrap();
+        // パスは深くても1回のリンクで辿れる
+        let deep = crawler.next_url().await.unwrap();
+        assert_eq!((deep.url.as_str(), deep.hops), ("http://example.com/a/b/c/d", 1));
+        crawler.complete(&deep, links(&["http://example.com/x"])).unwrap();
+        assert_eq!(crawler.next_url().await, None);
+
+        let crawler = TestCrawler::new("http://example.com/")
+            .options(|options| {
+                options.max_depth = 1;
+                options.depth_mode = DepthMode::Path;
+            })
+            .build();
+        let root = crawler.next_url().await.unwrap();
+        crawler.complete(&root, links(&["http://example.com/a/b/c/d", "http://example.com/x"])).unwrap();
+        assert_eq!(crawler.next_url().await.map(|queued| queued.url), Some("http://example.com/x".to_string()));
+
+        assert_eq!(path_depth("http://example.com"), 0);
+        assert_eq!(path_depth("http://example.com/a/b"), 2);
+        assert_eq!(path_depth("http://example.com/a?next=/b/c/d"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_breadth_first_levels() {
+        let crawler = TestCrawler::new("http://example.com/").build();
+        let root = crawler.next_url().await.unwrap();
+        crawler.complete(&root, links(&["http://example.com/a", "http://example.com/b"])).unwrap();
+        let a = crawler.next_url().await.unwrap();
+        let b = crawler.next_url().await.unwrap();
+        crawler.complete(&a, links(&["http://example.com/a/deeper"])).unwrap();
+
+        // /b を処理中の間は次の階層を取り出さない
+        let waiting = tokio::time::timeout(Duration::from_millis(50), crawler.next_url()).await;
+        assert!(waiting.is_err());
+
+        crawler.complete(&b, links(&["http://example.com/c"])).unwrap();
+        let next: Vec<(String, usize)> = [crawler.next_url().await.unwrap(), crawler.next_url().await.unwrap()].into_iter().map(|queued| (queued.url, queued.hops)).collect();
+        assert_eq!(next, vec![("http://example.com/a/deeper".to_string(), 2), ("http://example.com/c".to_string(), 2)]);
+    }
+
+    #[tokio::test]
+    async fn test_path_mode_does_not_wait_for_level() {
+        let crawler = TestCrawler::new("http://example.com/").options(|options| options.depth_mode = DepthMode::Path).build();
+        let root = crawler.next_url().await.unwrap();
+        crawler.complete(&root, links(&["http://example.com/a", "http://example.com/b"])).unwrap();
+        let a = crawler.next_url().await.unwrap();
+        let _b = crawler.next_url().await.unwrap();
+        crawler.complete(&a, links(&["http://example.com/a/deeper"])).unwrap();
+
+        // /b の処理中でも次の階層のURLを取り出す
+        let next = tokio::time::timeout(Duration::from_millis(50), crawler.next_url()).await.unwrap();
+        assert_eq!(next.map(|queued| queued.url), Some("http://example.com/a/deeper".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_redirect_target_is_visited() {
+        let crawler = TestCrawler::new("http://example.com/").build();
+        let root = crawler.next_url().await.unwrap();
+        // /old が /new/ にリダイレクトした後で、/new へのリンクが見つかった
+        crawler.mark_visited("http://example.com/new/");
+        crawler.complete(&root, links(&["http://example.com/new"])).unwrap();
+        assert_eq!(crawler.next_url().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_frontier_pattern_limit() {
+        let crawler = TestCrawler::new("http://example.com/").build();
+        crawler.next_url().await;
+        let news: Vec<String> = [1, 2, 3, 4, 5, 5].iter().map(|i| format!("http://example.com/news/{}", i)).collect();
+        crawler.complete(&queued("http://example.com/", 0), links(&news.iter().map(String::as_str).collect::<Vec<_>>())).unwrap();
+
+        // 同じパターンのURLは3件まで。超えた分は同じURLへのリンクが何度あっても1件と数える
+        assert_eq!(visited_count(&crawler), 4);
+        assert_eq!(crawler.pattern_report(), vec![PatternReport { pattern: r"/\d+".to_string(), crawled: 3, suppressed: 2 }]);
+    }
+
+    #[tokio::test]
+    async fn test_frontier_pattern_template_limit() {
+        let patterns = UrlPatterns::new(vec![UrlPattern::parse(r"/(news|blog)/(?P<id>[0-9]+) limit=1").unwrap()], 3);
+        let crawler = TestCrawler::new("http://example.com/").patterns(patterns).build();
+        crawler.next_url().await;
+        crawler.complete(&queued("http://example.com/", 0), links(&[
+            "http://example.com/news/1",
+            "http://example.com/news/2",
+            "http://example.com/blog/1",
+            "http://example.com/about",
+        ])).unwrap();
+
+        // /news/{id} と /blog/{id} は別のグループ。どのパターンにも一致しないURLは上限を受けない
+        assert_eq!(visited_count(&crawler), 4);
+        assert_eq!(crawler.pattern_report(), vec![PatternReport { pattern: "/news/{id}".to_string(), crawled: 1, suppressed: 1 }]);
+    }
+
+    #[tokio::test]
+    async fn test_frontier_skips_robots_disallowed() {
+        let robots = Robots::parse("User-agent: *\nDisallow: /private\n", "check404");
+        let crawler = TestCrawler::new("http://example.com/").robots(robots).build();
+        crawler.next_url().await;
+        crawler.complete(&queued("http://example.com/", 0), links(&[
+            "http://example.com/private/a",
+            "http://example.com/private/a",
+            "http://example.com/public",
+        ])).unwrap();
+
+        // 拒否されたURLは一度だけ記録され、キューには入らない
+        assert_eq!(crawler.skipped_count(), 1);
+        assert_eq!(crawler.next_url().await.map(|queued| queued.url), Some("http://example.com/public".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_frontier_external_links() {
+        let links = links(&[
+            "https://partner.example/a",
+            "https://partner.example/a/",
+            "http://example.com/b",
+        ]);
+
+        // 無効な場合、サイト外のリンクは破棄される
+        let crawler = TestCrawler::new("http://example.com/").build();
+        crawler.next_url().await;
+        crawler.complete(&queued("http://example.com/", 0), links.clone()).unwrap();
+        assert_eq!(external_count(&crawler), 0);
+
+        // 有効な場合は一度だけキューに入る
+        let crawler = TestCrawler::new("http://example.com/").check_external().build();
+        crawler.next_url().await;
+        crawler.complete(&queued("http://example.com/", 0), links).unwrap();
+        assert_eq!(external_count(&crawler), 1);
+        assert_eq!(crawler.next_url().await, Some(QueuedUrl { url: "https://partner.example/a".to_string(), external: true, hops: 1 }));
+        assert_eq!(crawler.next_url().await, Some(QueuedUrl { url: "http://example.com/b".to_string(), external: false, hops: 1 }));
+    }
+
+    #[tokio::test]
+    async fn test_frontier_scope_rules() {
+        let crawler = TestCrawler::new("http://example.com/")
+            .check_external()
+            .options(|options| {
+                options.include = vec![Regex::new("^http://example.com/docs/").unwrap()];
+                options.exclude = vec![Regex::new(r"\.pdf$").unwrap()];
+            })
+            .build();
+        crawler.next_url().await;
+        crawler.complete(&queued("http://example.com/", 0), links(&[
+            "http://example.com/docs/a",
+            "http://example.com/docs/manual.pdf",
+            "http://example.com/blog/",
+            "https://partner.example/a",
+            "https://partner.example/b.pdf",
+        ])).unwrap();
+
+        // include はサイト内のURLだけに、exclude はサイト外のリンクにも適用する
+        assert_eq!(crawler.next_url().await.map(|queued| queued.url), Some("http://example.com/docs/a".to_string()));
+        assert_eq!(crawler.next_url().await.map(|queued| queued.url), Some("https://partner.example/a".to_string()));
+        crawler.complete(&queued("http://example.com/docs/a", 1), Vec::new()).unwrap();
+        crawler.complete(&queued("https://partner.example/a", 1), Vec::new()).unwrap();
+        assert_eq!(crawler.next_url().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_findings_report_lists_referrers() {
+        let crawler = TestCrawler::new("http://example.com/").build();
+        crawler.next_url().await;
+        let link = Link { url: "http://example.com/gone".to_string(), href: "/gone".to_string(), anchor_text: "Old page".to_string() };
+        crawler.complete(&queued("http://example.com/", 0), vec![link.clone(), link.clone()]).unwrap();
+        crawler.next_url().await;
+        crawler.complete(&queued("http://example.com/gone", 1), Vec::new()).unwrap();
+        crawler.record_finding(Finding {
+            url: "http://example.com/gone".to_string(),
+            status: Some(404),
+            category: ErrorCategory::NotFound,
+            message: None,
+        });
+
+        // 同じリンク元は一度だけ記録される
+        let report = crawler.findings_report();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].1, vec![Referrer {
+            source_url: "http://example.com/".to_string(),
+            href: "/gone".to_string(),
+            anchor_text: "Old page".to_string(),
+        }]);
+    }
+
+    #[test]
+    fn test_extract_links_anchor_text() {
+        let html = r#"<a href="/docs/a.html">  Read
+            the <b>docs</b></a><a href="mailto:x@example.com">mail</a><a href="ftp://example.com/f">ftp</a>"#;
+        let links = extract_links("http://example.com/index.html", html).unwrap();
+
+        assert_eq!(links, vec![Link {
+            url: "http://example.com/docs/a.html".to_string(),
+            href: "/docs/a.html".to_string(),
+            anchor_text: "Read the docs".to_string(),
+        }]);
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_saved_frontier() {
+        let robots = || Robots::parse("User-agent: *\nDisallow: /private\n", "check404");
+        let storage = MemoryStorage::default();
+        let run_id = storage.clone().start_run("http://example.com/", "{}").unwrap();
+        let crawler = TestCrawler::new("http://example.com/").robots(robots()).check_external().storage(&storage).build();
+        crawler.next_url().await;
+        crawler.complete(&queued("http://example.com/", 0), links(&[
+            "http://example.com/a",
+            "http://example.com/news/1",
+            "http://example.com/news/2",
+            "http://example.com/news/3",
+            "http://example.com/news/4",
+            "http://example.com/private/x",
+            "https://partner.example/",
+        ])).unwrap();
+        crawler.next_url().await;
+        crawler.complete(&queued("http://example.com/a", 1), Vec::new()).unwrap();
+        // ここで中断して、/news/{1,2,3} と partner.example は辿り終えていない
+
+        // 異常終了して running のまま残った実行も --force-resume で続けられる
+        let resumed = storage.clone().resume_run(run_id, "{}", true).unwrap();
+        assert_eq!(resumed.start_urls, vec!["http://example.com/"]);
+        let crawler = TestCrawler::new("http://example.com/").robots(robots()).check_external().storage(&storage).build_unseeded();
+        crawler.restore(resumed);
+        crawler.seed("http://example.com/").unwrap();
+
+        let mut queued = Vec::new();
+        while let Some(next) = crawler.next_url().await {
+            queued.push(next.url.clone());
+            crawler.complete(&next, Vec::new()).unwrap();
+        }
+        assert_eq!(queued, vec!["http://example.com/news/1", "http://example.com/news/2", "http://example.com/news/3", "https://partner.example/"]);
+        assert_eq!(visited_count(&crawler), 5);
+        assert_eq!(crawler.skipped_count(), 1);
+        assert_eq!(external_count(&crawler), 1);
+        // パターンの上限で辿らなかった /news/4 も、中断しなかった場合と同じく報告する
+        assert_eq!(crawler.pattern_report(), vec![PatternReport { pattern: r"/\d+".to_string(), crawled: 3, suppressed: 1 }]);
+    }
+
+    #[tokio::test]
+    async fn test_resume_keeps_pattern_limits() {
+        let storage = MemoryStorage::default();
+        let run_id = storage.clone().start_run("http://example.com/1", "{}").unwrap();
+        let crawler = TestCrawler::new("http://example.com/1").storage(&storage).build();
+        let root = crawler.next_url().await.unwrap();
+        crawler.complete(&root, links(&["http://example.com/2", "http://example.com/3", "http://example.com/4", "http://example.com/5"])).unwrap();
+        let report = crawler.pattern_report();
+        assert_eq!(report, vec![PatternReport { pattern: r"/\d+".to_string(), crawled: 3, suppressed: 1 }]);
+
+        // 開始URLはパターンに一致しても上限に数えない
+        let crawler = TestCrawler::new("http://example.com/1").storage(&storage).build_unseeded();
+        crawler.restore(storage.clone().resume_run(run_id, "{}", true).unwrap());
+        crawler.seed("http://example.com/1").unwrap();
+        assert_eq!(crawler.pattern_report(), report);
+    }
+
+    #[tokio::test]
+    async fn test_resume_keeps_earlier_results() {
+        let mut storage = MemoryStorage::default();
+        let run_id = storage.start_run("http://example.com/", "{}").unwrap();
+        let page = |url: &str, status, error_category| db::PageRecord { check_url: url.to_string(), normalized_url: url.to_string(), domain: "example.com".to_string(), status, external: false, error_category, error_message: None, redirect_count: 0, attempts: 1, elapsed_ms: 5, hops: 1 };
+        let crawler = TestCrawler::new("http://example.com/").storage(&storage).build();
+
+        // check_page が保存する結果とリンクを書き込んでから、リンク先を辿る
+        let root = crawler.next_url().await.unwrap();
+        storage.insert_page(run_id, page("http://example.com/", 200, None)).unwrap();
+        storage.insert_links(run_id, "http://example.com/", vec![
+            db::LinkRecord { target_url: "http://example.com/gone".to_string(), href: "/gone/".to_string(), anchor_text: "Gone".to_string() },
+            db::LinkRecord { target_url: "http://example.com/later".to_string(), href: "/later".to_string(), anchor_text: "Later".to_string() },
+        ]).unwrap();
+        crawler.complete(&root, links(&["http://example.com/gone/", "http://example.com/later"])).unwrap();
+        let gone = crawler.next_url().await.unwrap();
+        storage.insert_page(run_id, page("http://example.com/gone/", 404, Some("not_found"))).unwrap();
+        crawler.complete(&gone, Vec::new()).unwrap();
+        // /later をチェックしている途中で中断した（結果は保存したが辿り終えていない）
+        crawler.next_url().await.unwrap();
+        storage.insert_page(run_id, page("http://example.com/later", 200, None)).unwrap();
+
+        let resumed = storage.resume_run(run_id, "{}", true).unwrap();
+        let crawler = TestCrawler::new("http://example.com/").storage(&storage).build_unseeded();
+        crawler.restore(resumed);
+
+        // 中断する前に見つかった 404 もリンク元と一緒に報告し、--fail-on の対象にする
+        let findings = crawler.findings_report();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].0.summary(), "404 Error: http://example.com/gone/");
+        assert_eq!(findings[0].1.iter().map(|referrer| referrer.href.as_str()).collect::<Vec<_>>(), vec!["/gone/"]);
+        assert!(findings::FailOn::parse("404").unwrap().matches(&findings[0].0));
+
+        // 辿り終えていない /later は結果を戻さず、もう一度チェックする
+        assert_eq!(crawler.results().iter().map(|result| result.url.as_str()).collect::<Vec<_>>(), vec!["http://example.com/", "http://example.com/gone/"]);
+        assert_eq!(crawler.next_url().await.map(|queued| queued.url), Some("http://example.com/later".to_string()));
+    }
+}
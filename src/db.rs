@@ -0,0 +1,678 @@
+use chrono::Utc;
+use crate::findings::ErrorCategory;
+use crate::normalize::Normalizer;
+use crate::BoxError;
+use rusqlite::{params, Connection, OpenFlags};
+use std::path::Path;
+
+// runs テーブルに保存する実行全体の集計
+pub struct RunTotals {
+    pub total_urls: usize,
+    pub errors: usize,
+    pub redirect_issues: usize,
+    pub external_checked: usize,
+    pub skipped_by_robots: usize,
+}
+
+// runs テーブルの1行
+#[derive(Debug, PartialEq)]
+pub struct RunRow {
+    pub id: i64,
+    pub start_urls: String,
+    pub options: String,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub total_urls: Option<i64>,
+    pub errors: Option<i64>,
+    pub redirect_issues: Option<i64>,
+    pub exit_status: String,
+}
+
+// pages テーブルに保存する1件分の結果
+#[derive(Clone, Debug)]
+pub struct PageRecord {
+    pub check_url: String,
+    // リンク元の対応付けに使う、正規化したURL
+    pub normalized_url: String,
+    pub domain: String,
+    pub status: u16,
+    pub external: bool,
+    pub error_category: Option<&'static str>,
+    pub error_message: Option<String>,
+    pub redirect_count: usize,
+    pub attempts: u32,
+    pub elapsed_ms: u64,
+    // 開始URLから辿ったリンクの数（--depth-mode path では最短とは限らない）
+    pub hops: usize,
+}
+
+// redirects テーブルに保存するリダイレクトの1ホップ
+#[derive(Clone, Debug)]
+pub struct RedirectRecord {
+    pub url: String,
+    pub status: u16,
+    pub location: Option<String>,
+}
+
+// links テーブルに保存するページ内リンク1件分
+#[derive(Clone, Debug)]
+pub struct LinkRecord {
+    // 正規化したリンク先のURL（pages.normalized_url と対応する）
+    pub target_url: String,
+    pub href: String,
+    pub anchor_text: String,
+}
+
+// frontier テーブルの1行。visited に入れたURLを、辿り終えたかどうかと一緒に保存する
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrontierRecord {
+    pub url: String,
+    pub kind: FrontierKind,
+    pub hops: usize,
+    // ページのチェックと、そこから見つかったリンクの保存が終わっていれば true
+    pub done: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrontierKind {
+    Internal,
+    External,
+    // robots.txt で除外したURL（チェックしない）
+    Robots,
+    // パターンの上限を超えたため辿らなかったURL（visited には入れない）
+    Suppressed,
+}
+
+impl FrontierKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FrontierKind::Internal => "internal",
+            FrontierKind::External => "external",
+            FrontierKind::Robots => "robots",
+            FrontierKind::Suppressed => "suppressed",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<FrontierKind> {
+        match kind {
+            "internal" => Some(FrontierKind::Internal),
+            "external" => Some(FrontierKind::External),
+            "robots" => Some(FrontierKind::Robots),
+            "suppressed" => Some(FrontierKind::Suppressed),
+            _ => None,
+        }
+    }
+}
+
+// 並行して書き込む間も読み出せるように WAL モードで開く
+pub fn open(path: &Path) -> Result<Connection, BoxError> {
+    configure(Connection::open(path)?)
+}
+
+// 既存のデータベースを読み出し専用で開く（report と diff 用）
+// パスを打ち間違えたときに、空のデータベースを作ってエラーのない結果を表示しないようにする
+// クロール中のデータベースも開くので、WAL の設定やマイグレーションの書き込みはしない
+pub fn open_existing(path: &Path) -> Result<Connection, BoxError> {
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    let conn = Connection::open_with_flags(path, flags).map_err(|e| format!("cannot open database {}: {}", path.display(), e))?;
+    let version = schema_version(&conn).map_err(|e| format!("cannot read database {}: {}", path.display(), e))?;
+    if version != SCHEMA_VERSION {
+        return Err(format!(
+            "{} has schema version {}, but this check404 reads version {} (run a crawl with --db {} to upgrade it)",
+            path.display(), version, SCHEMA_VERSION, path.display(),
+        ).into());
+    }
+    Ok(conn)
+}
+
+fn configure(conn: Connection) -> Result<Connection, BoxError> {
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    init_schema(&conn)?;
+    Ok(conn)
+}
+
+// 1つのバージョンから次のバージョンへのスキーマの変更
+type Migration = fn(&Connection) -> rusqlite::Result<()>;
+
+// 順番に適用するマイグレーション。MIGRATIONS[n] を適用するとバージョン n + 1 になる
+// schema_version より前の crawl_data.db には途中までのカラムが既にあることがあるので、
+// カラムの追加は add_column_if_missing で行う
+const MIGRATIONS: [Migration; 9] = [
+    migrate_v1_pages,
+    migrate_v2_links_and_errors,
+    migrate_v3_redirects,
+    migrate_v4_attempts,
+    migrate_v5_elapsed,
+    migrate_v6_runs,
+    migrate_v7_frontier,
+    migrate_v8_hops,
+    migrate_v9_normalized_urls,
+];
+
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+// 古い crawl_data.db を現在のスキーマまで順番に更新する
+pub(crate) fn init_schema(conn: &Connection) -> Result<(), BoxError> {
+    let version = schema_version(conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(format!("crawl_data.db has schema version {}, but this check404 only supports up to {}", version, SCHEMA_VERSION).into());
+    }
+
+    conn.execute("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)", [])?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let tx = conn.unchecked_transaction()?;
+        migration(&tx)?;
+        tx.execute("DELETE FROM schema_version", [])?;
+        tx.execute("INSERT INTO schema_version (version) VALUES (?1)", [index + 1])?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+// schema_version がなければ、pages があれば最初のスキーマ（バージョン1）、なければ空のデータベース
+// 読み出すだけなので、読み出し専用のデータベースにも使える
+fn schema_version(conn: &Connection) -> rusqlite::Result<u32> {
+    if table_exists(conn, "schema_version")? {
+        let version: Option<u32> = conn.query_row("SELECT MAX(version) FROM schema_version", [], |row| row.get(0))?;
+        if let Some(version) = version {
+            return Ok(version);
+        }
+    }
+    Ok(if table_exists(conn, "pages")? { 1 } else { 0 })
+}
+
+fn table_exists(conn: &Connection, table: &str) -> rusqlite::Result<bool> {
+    conn.query_row("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)", [table], |row| row.get(0))
+}
+
+fn migrate_v1_pages(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS pages (
+            id INTEGER PRIMARY KEY,
+            check_url TEXT NOT NULL,
+            domain TEXT NOT NULL,
+            status INTEGER NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn migrate_v2_links_and_errors(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "pages", "external", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "pages", "error_category", "TEXT")?;
+    add_column_if_missing(conn, "pages", "error_message", "TEXT")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS links (
+            id INTEGER PRIMARY KEY,
+            source_url TEXT NOT NULL,
+            target_url TEXT NOT NULL,
+            href TEXT NOT NULL,
+            anchor_text TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS links_target_url ON links (target_url)", [])?;
+    Ok(())
+}
+
+fn migrate_v3_redirects(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "pages", "redirect_count", "INTEGER NOT NULL DEFAULT 0")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS redirects (
+            id INTEGER PRIMARY KEY,
+            check_url TEXT NOT NULL,
+            hop INTEGER NOT NULL,
+            url TEXT NOT NULL,
+            status INTEGER NOT NULL,
+            location TEXT,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn migrate_v4_attempts(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "pages", "attempts", "INTEGER NOT NULL DEFAULT 1")
+}
+
+fn migrate_v5_elapsed(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "pages", "elapsed_ms", "INTEGER")
+}
+
+// 1回のクロールが runs の1行。exit_status は running で始まり、終了時に success か failed にする
+// run_id のない古い行は NULL のまま残す
+fn migrate_v6_runs(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS runs (
+            id INTEGER PRIMARY KEY,
+            start_urls TEXT NOT NULL,
+            options TEXT NOT NULL,
+            started_at TEXT NOT NULL,
+            finished_at TEXT,
+            total_urls INTEGER,
+            errors INTEGER,
+            redirect_issues INTEGER,
+            external_checked INTEGER,
+            skipped_by_robots INTEGER,
+            exit_status TEXT NOT NULL DEFAULT 'running'
+        )",
+        [],
+    )?;
+    add_column_if_missing(conn, "pages", "run_id", "INTEGER REFERENCES runs (id)")?;
+    add_column_if_missing(conn, "links", "run_id", "INTEGER REFERENCES runs (id)")?;
+    add_column_if_missing(conn, "redirects", "run_id", "INTEGER REFERENCES runs (id)")?;
+    conn.execute("CREATE INDEX IF NOT EXISTS pages_run_id ON pages (run_id)", [])?;
+    Ok(())
+}
+
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .iter()
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
+// --resume のために、キューに入れたURLを実行ごとに保存する
+fn migrate_v7_frontier(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS frontier (
+            id INTEGER PRIMARY KEY,
+            run_id INTEGER NOT NULL REFERENCES runs (id),
+            url TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            done INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS frontier_run_id_url ON frontier (run_id, url)", [])?;
+    Ok(())
+}
+
+// 開始URLから辿ったリンクの数。それより前の行は NULL（フロンティアは 0）のまま
+fn migrate_v8_hops(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "pages", "hops", "INTEGER")?;
+    add_column_if_missing(conn, "frontier", "hops", "INTEGER NOT NULL DEFAULT 0")
+}
+
+// 同じページを指す別の表記のリンクもリンク元として引けるよう、正規化したURLで対応付ける
+// 既存の行はデフォルトの正規化で埋める
+fn migrate_v9_normalized_urls(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "pages", "normalized_url", "TEXT")?;
+    let normalizer = Normalizer::default();
+    normalize_urls(conn, &normalizer, "SELECT id, check_url FROM pages", "UPDATE pages SET normalized_url = ?2 WHERE id = ?1")?;
+    normalize_urls(conn, &normalizer, "SELECT id, target_url FROM links", "UPDATE links SET target_url = ?2 WHERE id = ?1")
+}
+
+// select で (id, URL) を読み、正規化したURLを update の ?2 に渡す
+fn normalize_urls(conn: &Connection, normalizer: &Normalizer, select: &str, update: &str) -> rusqlite::Result<()> {
+    let rows: Vec<(i64, String)> = conn.prepare(select)?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut update = conn.prepare(update)?;
+    for (id, url) in rows {
+        update.execute(params![id, normalizer.normalize(&url)])?;
+    }
+    Ok(())
+}
+
+// 実行を開始し、runs.id を返す。options はコマンドラインの設定をJSONにしたもの
+pub fn start_run(conn: &Connection, start_urls: &str, options: &str) -> rusqlite::Result<i64> {
+    conn.execute(
+        "INSERT INTO runs (start_urls, options, started_at) VALUES (?1, ?2, ?3)",
+        params![start_urls, options, Utc::now().to_rfc3339()],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn finish_run(conn: &Connection, run_id: i64, totals: &RunTotals, exit_status: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE runs SET finished_at = ?2, total_urls = ?3, errors = ?4, redirect_issues = ?5, external_checked = ?6, skipped_by_robots = ?7, exit_status = ?8
+         WHERE id = ?1",
+        params![
+            run_id,
+            Utc::now().to_rfc3339(),
+            totals.total_urls,
+            totals.errors,
+            totals.redirect_issues,
+            totals.external_checked,
+            totals.skipped_by_robots,
+            exit_status,
+        ],
+    )?;
+    Ok(())
+}
+
+// 続きから実行するために、終了時刻と集計を消して実行中に戻す
+pub fn reopen_run(conn: &Connection, run_id: i64) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE runs SET finished_at = NULL, total_urls = NULL, errors = NULL, redirect_issues = NULL, external_checked = NULL, skipped_by_robots = NULL, exit_status = 'running'
+         WHERE id = ?1",
+        [run_id],
+    )?;
+    Ok(())
+}
+
+// 新しい順の実行の一覧
+pub fn list_runs(conn: &Connection) -> rusqlite::Result<Vec<RunRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, start_urls, options, started_at, finished_at, total_urls, errors, redirect_issues, exit_status FROM runs ORDER BY id DESC",
+    )?;
+    let runs = stmt.query_map([], |row| {
+        Ok(RunRow {
+            id: row.get(0)?,
+            start_urls: row.get(1)?,
+            options: row.get(2)?,
+            started_at: row.get(3)?,
+            finished_at: row.get(4)?,
+            total_urls: row.get(5)?,
+            errors: row.get(6)?,
+            redirect_issues: row.get(7)?,
+            exit_status: row.get(8)?,
+        })
+    })?;
+    runs.collect()
+}
+
+// 以下の insert_* は呼び出し側のトランザクションの中でまとめて実行する
+pub fn insert_page(conn: &Connection, run_id: i64, record: &PageRecord) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO pages (check_url, domain, status, updated_at, external, error_category, error_message, redirect_count, attempts, elapsed_ms, run_id, hops, normalized_url)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+    )?;
+    stmt.execute(params![
+        record.check_url,
+        record.domain,
+        record.status,
+        Utc::now().to_rfc3339(),
+        record.external,
+        record.error_category,
+        record.error_message,
+        record.redirect_count,
+        record.attempts,
+        record.elapsed_ms,
+        run_id,
+        record.hops,
+        record.normalized_url,
+    ])?;
+    Ok(())
+}
+
+// 1ページ分のリンクを保存する
+pub fn insert_links(conn: &Connection, run_id: i64, source_url: &str, links: &[LinkRecord]) -> rusqlite::Result<()> {
+    let current_time = Utc::now().to_rfc3339();
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO links (source_url, target_url, href, anchor_text, updated_at, run_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )?;
+    for link in links {
+        stmt.execute(params![source_url, link.target_url, link.href, link.anchor_text, current_time, run_id])?;
+    }
+    Ok(())
+}
+
+// 1件のURLのリダイレクトチェーンを順番に保存する
+pub fn insert_redirects(conn: &Connection, run_id: i64, check_url: &str, hops: &[RedirectRecord]) -> rusqlite::Result<()> {
+    let current_time = Utc::now().to_rfc3339();
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO redirects (check_url, hop, url, status, location, updated_at, run_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )?;
+    for (index, hop) in hops.iter().enumerate() {
+        stmt.execute(params![check_url, index, hop.url, hop.status, hop.location, current_time, run_id])?;
+    }
+    Ok(())
+}
+
+pub fn insert_frontier(conn: &Connection, run_id: i64, records: &[FrontierRecord]) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare_cached("INSERT INTO frontier (run_id, url, kind, hops, done) VALUES (?1, ?2, ?3, ?4, ?5)")?;
+    for record in records {
+        stmt.execute(params![run_id, record.url, record.kind.as_str(), record.hops, record.done])?;
+    }
+    Ok(())
+}
+
+pub fn mark_done(conn: &Connection, run_id: i64, url: &str) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare_cached("UPDATE frontier SET done = 1 WHERE run_id = ?1 AND url = ?2")?;
+    stmt.execute(params![run_id, url])?;
+    Ok(())
+}
+
+// 辿り終えていないURLの結果・リンク・リダイレクトを消す
+// --resume でもう一度チェックするので、残しておくと同じ実行に同じURLの行が2つできる
+pub fn discard_unfinished(conn: &Connection, run_id: i64) -> rusqlite::Result<()> {
+    let unfinished = "SELECT url FROM frontier WHERE run_id = ?1 AND done = 0";
+    conn.execute(&format!("DELETE FROM pages WHERE run_id = ?1 AND check_url IN ({})", unfinished), [run_id])?;
+    conn.execute(&format!("DELETE FROM links WHERE run_id = ?1 AND source_url IN ({})", unfinished), [run_id])?;
+    conn.execute(&format!("DELETE FROM redirects WHERE run_id = ?1 AND check_url IN ({})", unfinished), [run_id])?;
+    Ok(())
+}
+
+// キューに入れた順のフロンティア
+pub fn load_frontier(conn: &Connection, run_id: i64) -> rusqlite::Result<Vec<FrontierRecord>> {
+    let mut stmt = conn.prepare("SELECT url, kind, hops, done FROM frontier WHERE run_id = ?1 ORDER BY id")?;
+    let rows = stmt.query_map([run_id], |row| {
+        let kind: String = row.get(1)?;
+        Ok(FrontierRecord {
+            url: row.get(0)?,
+            kind: FrontierKind::parse(&kind).ok_or_else(|| rusqlite::Error::InvalidColumnType(1, kind, rusqlite::types::Type::Text))?,
+            hops: row.get(2)?,
+            done: row.get(3)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// 以下の load_* は --resume で、中断する前の結果を戻すのに使う（保存した順）
+pub fn load_pages(conn: &Connection, run_id: i64) -> rusqlite::Result<Vec<PageRecord>> {
+    let mut stmt = conn.prepare(
+        "SELECT check_url, COALESCE(normalized_url, check_url), domain, status, external, error_category, error_message, redirect_count, attempts, COALESCE(elapsed_ms, 0), COALESCE(hops, 0)
+         FROM pages WHERE run_id = ?1 ORDER BY id",
+    )?;
+    let rows = stmt.query_map([run_id], |row| {
+        let error_category: Option<String> = row.get(5)?;
+        Ok(PageRecord {
+            check_url: row.get(0)?,
+            normalized_url: row.get(1)?,
+            domain: row.get(2)?,
+            status: row.get(3)?,
+            external: row.get(4)?,
+            error_category: error_category.and_then(|category| ErrorCategory::parse(&category)).map(|category| category.as_str()),
+            error_message: row.get(6)?,
+            redirect_count: row.get(7)?,
+            attempts: row.get(8)?,
+            elapsed_ms: row.get(9)?,
+            hops: row.get(10)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// (リンク元のURL, リンク)
+pub fn load_links(conn: &Connection, run_id: i64) -> rusqlite::Result<Vec<(String, LinkRecord)>> {
+    let mut stmt = conn.prepare("SELECT source_url, target_url, href, anchor_text FROM links WHERE run_id = ?1 ORDER BY id")?;
+    let rows = stmt.query_map([run_id], |row| {
+        Ok((row.get(0)?, LinkRecord { target_url: row.get(1)?, href: row.get(2)?, anchor_text: row.get(3)? }))
+    })?;
+    rows.collect()
+}
+
+// (check_url, リダイレクトチェーン)
+pub fn load_redirects(conn: &Connection, run_id: i64) -> rusqlite::Result<Vec<(String, Vec<RedirectRecord>)>> {
+    let mut stmt = conn.prepare("SELECT check_url, hop, url, status, location FROM redirects WHERE run_id = ?1 ORDER BY id")?;
+    let rows = stmt.query_map([run_id], |row| {
+        let hop: usize = row.get(1)?;
+        Ok((row.get::<_, String>(0)?, hop, RedirectRecord { url: row.get(2)?, status: row.get(3)?, location: row.get(4)? }))
+    })?;
+
+    // チェーンは1件のURLごとにまとめて保存しているので、hop が 0 の行から次のチェーンになる
+    let mut chains: Vec<(String, Vec<RedirectRecord>)> = Vec::new();
+    for row in rows {
+        let (check_url, hop, record) = row?;
+        match chains.last_mut() {
+            Some((url, records)) if hop > 0 && *url == check_url => records.push(record),
+            _ => chains.push((check_url, vec![record])),
+        }
+    }
+    Ok(chains)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrates_v1_database() -> Result<(), BoxError> {
+        let conn = Connection::open_in_memory()?;
+        // schema_version のない、最初のバージョンの crawl_data.db
+        conn.execute(
+            "CREATE TABLE pages (
+                id INTEGER PRIMARY KEY,
+                check_url TEXT NOT NULL,
+                domain TEXT NOT NULL,
+                status INTEGER NOT NULL,
+                updated_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute("INSERT INTO pages (check_url, domain, status, updated_at) VALUES ('https://example.com/old/', 'example.com', 404, '2024-01-01T00:00:00Z')", [])?;
+        init_schema(&conn)?;
+        assert_eq!(schema_version(&conn)?, SCHEMA_VERSION);
+
+        // 古い行は残り、追加したカラムはデフォルト値になる
+        let (external, redirect_count, attempts, run_id, normalized_url): (bool, i64, i64, Option<i64>, String) = conn.query_row(
+            "SELECT external, redirect_count, attempts, run_id, normalized_url FROM pages WHERE check_url = 'https://example.com/old/'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+        )?;
+        assert!(!external);
+        assert_eq!((redirect_count, attempts, run_id), (0, 1, None));
+        assert_eq!(normalized_url, "https://example.com/old");
+
+        let run_id = start_run(&conn, "https://example.com/", "{}")?;
+        insert_page(&conn, run_id, &PageRecord { check_url: "https://partner.example/".to_string(), normalized_url: "https://partner.example".to_string(), domain: "partner.example".to_string(), status: 404, external: true, error_category: Some("not_found"), error_message: None, redirect_count: 0, attempts: 1, elapsed_ms: 12, hops: 1 })?;
+        let (external, error_category): (bool, String) = conn.query_row("SELECT external, error_category FROM pages WHERE run_id = ?1", [run_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        assert!(external);
+        assert_eq!(error_category, "not_found");
+
+        // 2回目は何もしない
+        init_schema(&conn)?;
+        let versions: i64 = conn.query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0))?;
+        assert_eq!(versions, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_existing_does_not_create() -> Result<(), BoxError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("typo.db");
+        assert!(open_existing(&path).is_err());
+        assert!(!path.exists());
+
+        open(&path)?;
+        let conn = open_existing(&path)?;
+        // 読み出し専用で開く
+        assert!(conn.execute("DELETE FROM runs", []).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_existing_does_not_migrate() -> Result<(), BoxError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("old.db");
+        let conn = Connection::open(&path)?;
+        conn.execute("CREATE TABLE pages (id INTEGER PRIMARY KEY, check_url TEXT NOT NULL, domain TEXT NOT NULL, status INTEGER NOT NULL, updated_at TEXT NOT NULL)", [])?;
+        drop(conn);
+
+        let error = open_existing(&path).err().unwrap().to_string();
+        assert!(error.contains("has schema version 1"), "{}", error);
+        assert_eq!(schema_version(&Connection::open(&path)?)?, 1);
+        assert!(!table_exists(&Connection::open(&path)?, "schema_version")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_newer_schema() -> Result<(), BoxError> {
+        let conn = Connection::open_in_memory()?;
+        init_schema(&conn)?;
+        conn.execute("UPDATE schema_version SET version = ?1", [SCHEMA_VERSION + 1])?;
+        assert!(init_schema(&conn).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_links() -> Result<(), BoxError> {
+        let conn = Connection::open_in_memory()?;
+        init_schema(&conn)?;
+
+        let run_id = start_run(&conn, "http://example.com/", "{}")?;
+        insert_links(&conn, run_id, "http://example.com/", &[
+            LinkRecord { target_url: "http://example.com/gone".to_string(), href: "/gone".to_string(), anchor_text: "Old page".to_string() },
+            LinkRecord { target_url: "http://example.com/docs".to_string(), href: "docs".to_string(), anchor_text: "Docs".to_string() },
+        ])?;
+
+        let (source_url, anchor_text): (String, String) = conn.query_row(
+            "SELECT source_url, anchor_text FROM links WHERE target_url = ?1",
+            ["http://example.com/gone"],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        assert_eq!(source_url, "http://example.com/");
+        assert_eq!(anchor_text, "Old page");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_run_results() -> Result<(), BoxError> {
+        let conn = Connection::open_in_memory()?;
+        init_schema(&conn)?;
+
+        let other = start_run(&conn, "http://example.com/", "{}")?;
+        let run_id = start_run(&conn, "http://example.com/", "{}")?;
+        let page = PageRecord { check_url: "http://example.com/old/".to_string(), normalized_url: "http://example.com/old".to_string(), domain: "example.com".to_string(), status: 404, external: false, error_category: Some("not_found"), error_message: None, redirect_count: 1, attempts: 2, elapsed_ms: 7, hops: 1 };
+        insert_page(&conn, other, &page)?;
+        insert_page(&conn, run_id, &page)?;
+        insert_links(&conn, run_id, "http://example.com/", &[LinkRecord { target_url: "http://example.com/old".to_string(), href: "old/".to_string(), anchor_text: "Old".to_string() }])?;
+        let hop = |url: &str, location: &str| RedirectRecord { url: url.to_string(), status: 301, location: Some(location.to_string()) };
+        insert_redirects(&conn, run_id, "http://example.com/a", &[hop("http://example.com/a", "/b"), hop("http://example.com/b", "/c")])?;
+        insert_redirects(&conn, run_id, "http://example.com/a", &[hop("http://example.com/a", "/d")])?;
+
+        let pages = load_pages(&conn, run_id)?;
+        assert_eq!(pages.len(), 1);
+        assert_eq!((pages[0].normalized_url.as_str(), pages[0].error_category, pages[0].attempts, pages[0].hops), ("http://example.com/old", Some("not_found"), 2, 1));
+        let links = load_links(&conn, run_id)?;
+        assert_eq!((links[0].0.as_str(), links[0].1.target_url.as_str()), ("http://example.com/", "http://example.com/old"));
+        // 同じURLのチェーンを2回保存した場合も、別のチェーンとして読む
+        let chains: Vec<(String, usize)> = load_redirects(&conn, run_id)?.into_iter().map(|(url, hops)| (url, hops.len())).collect();
+        assert_eq!(chains, vec![("http://example.com/a".to_string(), 2), ("http://example.com/a".to_string(), 1)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_runs() -> Result<(), BoxError> {
+        let conn = Connection::open_in_memory()?;
+        init_schema(&conn)?;
+
+        let first = start_run(&conn, "https://example.com/", r#"{"depth":3}"#)?;
+        let totals = RunTotals { total_urls: 10, errors: 2, redirect_issues: 1, external_checked: 0, skipped_by_robots: 0 };
+        finish_run(&conn, first, &totals, "failed")?;
+        let second = start_run(&conn, "https://example.com/", r#"{"depth":3}"#)?;
+
+        let runs = list_runs(&conn)?;
+        assert_eq!(runs.iter().map(|run| run.id).collect::<Vec<_>>(), vec![second, first]);
+        assert_eq!(runs[0].exit_status, "running");
+        assert_eq!(runs[0].finished_at, None);
+        assert_eq!(runs[1].exit_status, "failed");
+        assert_eq!(runs[1].errors, Some(2));
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+use crate::cli::DiffArgs;
+use crate::report::{self, StoredPage};
+use crate::{db, BoxError};
+use clap::ValueEnum;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+// diff サブコマンドの出力形式
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum DiffFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+// 2つの実行でステータスが変わった1件のURL
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Change {
+    pub url: String,
+    // 古い実行でチェックされていなければ None
+    pub old_status: Option<u16>,
+    // 新しい実行でチェックされていなければ None
+    pub new_status: Option<u16>,
+    pub error_category: Option<String>,
+    pub redirect_count: usize,
+    pub referrers: Vec<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct RunDiff {
+    pub old_run: i64,
+    pub new_run: i64,
+    // 新しくエラーになったURL（新しい実行で初めて見つかったものを含む）
+    pub newly_broken: Vec<Change>,
+    // エラーが直ったURL
+    pub fixed: Vec<Change>,
+    // 新しくリダイレクトされるようになったURL
+    pub new_redirects: Vec<Change>,
+    // 新しい実行ではクロールされなかったURL
+    pub disappeared: Vec<Change>,
+    // その他のステータスの変化（404 から 410 など）
+    pub changed: Vec<Change>,
+}
+
+// 2つの実行を比較する
+pub fn run(args: &DiffArgs) -> Result<ExitCode, BoxError> {
+    let conn = db::open_existing(&args.db)?;
+    let (old_run, new_run) = select_runs(&conn, &args.db, args.old_run, args.new_run)?;
+    let diff = compare(
+        old_run,
+        new_run,
+        &report::latest_pages(&conn, Some(old_run))?,
+        &report::latest_pages(&conn, Some(new_run))?,
+        |url| report::referrers(&conn, Some(new_run), url).map(|rows| rows.into_iter().map(|(source_url, _, _)| source_url).collect()),
+    )?;
+
+    let mut out = io::stdout().lock();
+    match args.format {
+        DiffFormat::Text => write_text(&mut out, &diff)?,
+        DiffFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, &diff)?;
+            writeln!(out)?;
+        }
+    }
+
+    if args.fail_on_new && !diff.newly_broken.is_empty() {
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// NEW を省略すると最後まで終わった最新の実行、OLD を省略すると NEW より前に最後まで終わった実行
+// 実行中や中断した実行は一部のURLしかチェックしていないので、指定しない限り比べない
+fn select_runs(conn: &Connection, db_path: &Path, old_run: Option<i64>, new_run: Option<i64>) -> Result<(i64, i64), String> {
+    let runs = db::list_runs(conn).map_err(|e| e.to_string())?;
+    let exists = |run_id: i64| runs.iter().any(|run| run.id == run_id);
+    // runs は新しい順
+    let mut finished = runs.iter().filter(|run| matches!(run.exit_status.as_str(), "success" | "failed")).map(|run| run.id);
+    let new_run = match new_run {
+        Some(run_id) if exists(run_id) => run_id,
+        Some(run_id) => return Err(format!("no run with id {}", run_id)),
+        None => finished.next().ok_or(format!("no finished runs in {}", db_path.display()))?,
+    };
+    let old_run = match old_run {
+        Some(run_id) if exists(run_id) => run_id,
+        Some(run_id) => return Err(format!("no run with id {}", run_id)),
+        None => finished.find(|&run_id| run_id < new_run).ok_or(format!("no finished run before run {} to compare with", new_run))?,
+    };
+    Ok((old_run, new_run))
+}
+
+fn compare<F>(old_run: i64, new_run: i64, old_pages: &[StoredPage], new_pages: &[StoredPage], referrers: F) -> rusqlite::Result<RunDiff>
+where
+    F: Fn(&str) -> rusqlite::Result<Vec<String>>,
+{
+    let old: HashMap<&str, &StoredPage> = old_pages.iter().map(|page| (page.check_url.as_str(), page)).collect();
+    let new: HashMap<&str, &StoredPage> = new_pages.iter().map(|page| (page.check_url.as_str(), page)).collect();
+    let urls: BTreeSet<&str> = old.keys().chain(new.keys()).copied().collect();
+
+    let mut diff = RunDiff { old_run, new_run, ..RunDiff::default() };
+    for url in urls {
+        let old_page = old.get(url).copied();
+        let new_page = new.get(url).copied();
+        let list = match (old_page, new_page) {
+            (Some(_), None) => &mut diff.disappeared,
+            (_, Some(new_page)) if new_page.error_category.is_some() => match old_page {
+                Some(old_page) if old_page.error_category.is_some() => {
+                    if old_page.status == new_page.status && old_page.error_category == new_page.error_category {
+                        continue;
+                    }
+                    &mut diff.changed
+                }
+                _ => &mut diff.newly_broken,
+            },
+            (Some(old_page), Some(_)) if old_page.error_category.is_some() => &mut diff.fixed,
+            (old_page, Some(new_page)) if new_page.redirect_count > 0 && old_page.is_none_or(|old_page| old_page.redirect_count == 0) => &mut diff.new_redirects,
+            (Some(old_page), Some(new_page)) if old_page.status != new_page.status => &mut diff.changed,
+            _ => continue,
+        };
+
+        let current = new_page.or(old_page).expect("url comes from one of the runs");
+        list.push(Change {
+            url: url.to_string(),
+            old_status: old_page.map(|page| page.status),
+            new_status: new_page.map(|page| page.status),
+            error_category: new_page.and_then(|page| page.error_category.clone()),
+            redirect_count: current.redirect_count,
+            referrers: match new_page {
+                Some(new_page) => referrers(&new_page.normalized_url)?,
+                None => Vec::new(),
+            },
+        });
+    }
+    Ok(diff)
+}
+
+fn write_text(out: &mut dyn Write, diff: &RunDiff) -> io::Result<()> {
+    writeln!(out, "Comparing run #{} -> #{}", diff.old_run, diff.new_run)?;
+    let sections = [
+        ("Newly broken", &diff.newly_broken),
+        ("Fixed", &diff.fixed),
+        ("New redirects", &diff.new_redirects),
+        ("Disappeared", &diff.disappeared),
+        ("Status changed", &diff.changed),
+    ];
+    for (title, changes) in sections {
+        if changes.is_empty() {
+            continue;
+        }
+        writeln!(out)?;
+        writeln!(out, "{} ({}):", title, changes.len())?;
+        for change in changes {
+            writeln!(out, "  {} {} -> {}", change.url, status_label(change.old_status), status_label(change.new_status))?;
+            for referrer in &change.referrers {
+                writeln!(out, "    found on: {}", referrer)?;
+            }
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(
+        out,
+        "Newly broken: {}, Fixed: {}, New redirects: {}, Disappeared: {}, Status changed: {}",
+        diff.newly_broken.len(),
+        diff.fixed.len(),
+        diff.new_redirects.len(),
+        diff.disappeared.len(),
+        diff.changed.len(),
+    )
+}
+
+// ステータスを受け取れなかったものは network、チェックされていないものは - と表示する
+fn status_label(status: Option<u16>) -> String {
+    match status {
+        Some(0) => "network".to_string(),
+        Some(status) => status.to_string(),
+        None => "-".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(url: &str, status: u16, error_category: Option<&str>, redirect_count: usize) -> StoredPage {
+        StoredPage {
+            check_url: url.to_string(),
+            normalized_url: url.to_string(),
+            domain: "example.com".to_string(),
+            status,
+            updated_at: String::new(),
+            external: false,
+            error_category: error_category.map(str::to_string),
+            error_message: None,
+            redirect_count,
+            attempts: 1,
+            elapsed_ms: None,
+        }
+    }
+
+    fn urls(changes: &[Change]) -> Vec<&str> {
+        changes.iter().map(|change| change.url.as_str()).collect()
+    }
+
+    #[test]
+    fn test_compare_runs() {
+        let old = vec![
+            page("http://example.com/", 200, None, 0),
+            page("http://example.com/a", 200, None, 0),
+            page("http://example.com/b", 404, Some("not_found"), 0),
+            page("http://example.com/c", 200, None, 0),
+            page("http://example.com/d", 200, None, 0),
+            page("http://example.com/e", 404, Some("not_found"), 0),
+            page("http://example.com/f", 503, Some("server_error"), 0),
+        ];
+        let new = vec![
+            page("http://example.com/", 200, None, 0),
+            page("http://example.com/a", 404, Some("not_found"), 0),
+            page("http://example.com/b", 200, None, 0),
+            page("http://example.com/c", 200, None, 1),
+            page("http://example.com/e", 410, Some("client_error"), 0),
+            page("http://example.com/f", 503, Some("server_error"), 0),
+            page("http://example.com/g", 0, Some("dns"), 0),
+        ];
+
+        let diff = compare(1, 2, &old, &new, |url| Ok(vec![format!("{}-referrer", url)])).unwrap();
+        assert_eq!(urls(&diff.newly_broken), vec!["http://example.com/a", "http://example.com/g"]);
+        assert_eq!(urls(&diff.fixed), vec!["http://example.com/b"]);
+        assert_eq!(urls(&diff.new_redirects), vec!["http://example.com/c"]);
+        assert_eq!(urls(&diff.disappeared), vec!["http://example.com/d"]);
+        assert_eq!(urls(&diff.changed), vec!["http://example.com/e"]);
+
+        assert_eq!(diff.newly_broken[0].old_status, Some(200));
+        assert_eq!(diff.newly_broken[0].referrers, vec!["http://example.com/a-referrer"]);
+        assert_eq!(diff.newly_broken[1].old_status, None);
+        assert!(diff.disappeared[0].referrers.is_empty());
+    }
+
+    #[test]
+    fn test_select_runs() -> Result<(), BoxError> {
+        let conn = Connection::open_in_memory()?;
+        db::init_schema(&conn)?;
+        let path = Path::new("runs.db");
+        assert_eq!(select_runs(&conn, path, None, None), Err("no finished runs in runs.db".to_string()));
+
+        let totals = || db::RunTotals { total_urls: 1, errors: 0, redirect_issues: 0, external_checked: 0, skipped_by_robots: 0 };
+        for exit_status in ["success", "failed", "interrupted", "success", "running"] {
+            let run_id = db::start_run(&conn, "http://example.com/", "{}")?;
+            if exit_status != "running" {
+                db::finish_run(&conn, run_id, &totals(), exit_status)?;
+            }
+        }
+        // 中断した実行と実行中の実行は、指定しない限り比べない
+        assert_eq!(select_runs(&conn, path, None, None)?, (2, 4));
+        assert_eq!(select_runs(&conn, path, None, Some(2))?, (1, 2));
+        assert_eq!(select_runs(&conn, path, Some(3), Some(5))?, (3, 5));
+        assert!(select_runs(&conn, path, None, Some(1)).is_err());
+        assert!(select_runs(&conn, path, Some(9), None).is_err());
+        Ok(())
+    }
+}
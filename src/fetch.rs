@@ -0,0 +1,241 @@
+use crate::rate_limit::RateLimiter;
+use reqwest::header::LOCATION;
+use reqwest::{Client, Method, Response};
+use std::collections::HashSet;
+use std::time::Duration;
+use url::Url;
+
+// 何回リダイレクトが続いたら打ち切るか（--max-redirects による警告とは別の上限）
+const MAX_FOLLOW: usize = 20;
+
+pub const DEFAULT_USER_AGENT: &str = concat!("check404/", env!("CARGO_PKG_VERSION"));
+
+// 全てのリクエストで共有する HTTP クライアントの設定
+pub struct HttpOptions {
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    pub timeout: Duration,
+    pub user_agent: String,
+    pub pool_idle_timeout: Duration,
+    pub pool_max_idle_per_host: usize,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(30),
+            timeout: Duration::from_secs(60),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            pool_idle_timeout: Duration::from_secs(90),
+            pool_max_idle_per_host: 16,
+        }
+    }
+}
+
+// リダイレクトチェーンを記録するため、リダイレクトは自動では辿らない
+pub fn build_client(options: &HttpOptions) -> reqwest::Result<Client> {
+    Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent(options.user_agent.as_str())
+        .connect_timeout(options.connect_timeout)
+        .read_timeout(options.read_timeout)
+        .timeout(options.timeout)
+        .pool_idle_timeout(options.pool_idle_timeout)
+        .pool_max_idle_per_host(options.pool_max_idle_per_host)
+        .tcp_keepalive(Duration::from_secs(60))
+        .build()
+}
+
+// リダイレクトチェーンの1ホップ
+#[derive(Clone, Debug, PartialEq)]
+pub struct RedirectHop {
+    pub url: String,
+    pub status: u16,
+    pub location: Option<String>,
+}
+
+// リダイレクトチェーンの問題
+#[derive(Clone, Debug, PartialEq)]
+pub enum RedirectIssue {
+    TooLong(usize),
+    Loop,
+    EndsInError(u16),
+    // robots.txt で拒否されたURLへのリダイレクト（その先は取得しない）
+    Disallowed,
+}
+
+impl RedirectIssue {
+    pub fn describe(&self) -> String {
+        match self {
+            RedirectIssue::TooLong(hops) => format!("redirect chain too long ({} hops)", hops),
+            RedirectIssue::Loop => "redirect loop".to_string(),
+            RedirectIssue::EndsInError(status) => format!("redirect ends in {}", status),
+            RedirectIssue::Disallowed => "redirect target disallowed by robots.txt".to_string(),
+        }
+    }
+}
+
+pub struct Fetched {
+    // リダイレクトでない最終レスポンス。ループや打ち切りの場合は None
+    pub response: Option<Response>,
+    pub hops: Vec<RedirectHop>,
+    pub loop_detected: bool,
+    // リダイレクト先が robots.txt で拒否されていた（response はリダイレクトのレスポンス）
+    pub disallowed: bool,
+    // read_body で読んだ最終レスポンスの本文（読んでいなければ None）
+    pub body: Option<Result<String, reqwest::Error>>,
+}
+
+impl Fetched {
+    // 最終レスポンスの本文を読む。ステータスやURLを後で使えるよう、response は残したまま読む
+    pub async fn read_body(&mut self) {
+        let Some(response) = self.response.as_mut() else {
+            return;
+        };
+        let mut bytes = Vec::new();
+        let result = loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) => bytes.extend_from_slice(&chunk),
+                Ok(None) => break Ok(String::from_utf8_lossy(&bytes).into_owned()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.body = Some(result);
+    }
+
+    // チェーンの長さの上限を超えたもの、ループ、エラーで終わるものを問題として返す
+    pub fn redirect_issues(&self, max_redirects: usize) -> Vec<RedirectIssue> {
+        let final_status = self.response.as_ref().map(|response| response.status().as_u16());
+        redirect_issues(&self.hops, final_status, self.loop_detected, self.disallowed, max_redirects)
+    }
+}
+
+// 保存済みのリダイレクトチェーンから、取得したときと同じ問題を求める（--resume で使う）
+// final_status は最後に受け取ったレスポンスのステータス。ループや打ち切り、robots.txt で最後まで辿らなかった場合は None
+// allowed は fetch と同じく、リダイレクト先を取得してよいかどうかを返す
+pub fn stored_redirect_issues(hops: &[RedirectHop], final_status: Option<u16>, max_redirects: usize, allowed: impl Fn(&Url) -> bool) -> Vec<RedirectIssue> {
+    let next = hops.last().and_then(|hop| {
+        let url = Url::parse(&hop.url).ok()?;
+        resolve_location(&url, hop.location.as_deref()?)
+    });
+    let loop_detected = final_status.is_none() && next.as_ref().is_some_and(|next| hops.iter().any(|hop| hop.url == next.as_str()));
+    // 最後まで辿らなかったチェーンの次のURLが拒否されているなら、robots.txt で拒否された
+    // （以前は拒否されたリダイレクトを 3xx で終わる結果として保存していた）
+    let disallowed = match final_status {
+        None => !loop_detected && next.as_ref().is_some_and(|next| !allowed(next)),
+        Some(status) => next.is_some() && (300..400).contains(&status),
+    };
+    redirect_issues(hops, final_status, loop_detected, disallowed, max_redirects)
+}
+
+fn redirect_issues(hops: &[RedirectHop], final_status: Option<u16>, loop_detected: bool, disallowed: bool, max_redirects: usize) -> Vec<RedirectIssue> {
+    let mut issues = Vec::new();
+    if hops.is_empty() {
+        return issues;
+    }
+    if hops.len() > max_redirects || (final_status.is_none() && !loop_detected && !disallowed) {
+        issues.push(RedirectIssue::TooLong(hops.len()));
+    }
+    if loop_detected {
+        issues.push(RedirectIssue::Loop);
+    }
+    if disallowed {
+        issues.push(RedirectIssue::Disallowed);
+    }
+    if let Some(status) = final_status.filter(|status| (400..600).contains(status)) {
+        issues.push(RedirectIssue::EndsInError(status));
+    }
+    issues
+}
+
+// Location をリダイレクト元のURLを基準に解決する（フラグメントは取り除く）
+fn resolve_location(current: &Url, location: &str) -> Option<Url> {
+    let mut next = current.join(location).ok()?;
+    next.set_fragment(None);
+    Some(next)
+}
+
+// リダイレクトを1ホップずつ辿り、チェーンを記録する
+// client は build_client で作ったもの（リダイレクトを自動で辿らない）を渡す
+// 各ホップのリクエストの前に rate_limiter で待ち、allowed が false を返すリダイレクト先は取得しない
+pub async fn fetch(client: &Client, method: Method, url: &Url, rate_limiter: &RateLimiter, allowed: impl Fn(&Url) -> bool) -> Result<Fetched, reqwest::Error> {
+    let mut hops = Vec::new();
+    let mut seen = HashSet::new();
+    let mut current = url.clone();
+    seen.insert(current.to_string());
+
+    loop {
+        rate_limiter.acquire(&current).await;
+        let response = client.request(method.clone(), current.as_str()).send().await?;
+        let status = response.status();
+
+        if !status.is_redirection() {
+            return Ok(Fetched { response: Some(response), hops, loop_detected: false, disallowed: false, body: None });
+        }
+
+        let location = response.headers().get(LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let next = location.as_deref().and_then(|location| resolve_location(&current, location));
+        hops.push(RedirectHop { url: current.to_string(), status: status.as_u16(), location });
+
+        // Location がなければ 3xx をそのまま最終レスポンスとして扱う
+        let Some(next) = next else {
+            return Ok(Fetched { response: Some(response), hops, loop_detected: false, disallowed: false, body: None });
+        };
+        if !seen.insert(next.to_string()) {
+            return Ok(Fetched { response: None, hops, loop_detected: true, disallowed: false, body: None });
+        }
+        if hops.len() >= MAX_FOLLOW {
+            return Ok(Fetched { response: None, hops, loop_detected: false, disallowed: false, body: None });
+        }
+        if !allowed(&next) {
+            return Ok(Fetched { response: Some(response), hops, loop_detected: false, disallowed: true, body: None });
+        }
+        current = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hop(url: &str) -> RedirectHop {
+        RedirectHop { url: url.to_string(), status: 301, location: Some("/next".to_string()) }
+    }
+
+    #[test]
+    fn test_redirect_issues() {
+        let no_redirect = Fetched { response: None, hops: Vec::new(), loop_detected: false, disallowed: false, body: None };
+        assert!(no_redirect.redirect_issues(5).is_empty());
+
+        let looped = Fetched { response: None, hops: vec![hop("http://example.com/a"), hop("http://example.com/b")], loop_detected: true, disallowed: false, body: None };
+        assert_eq!(looped.redirect_issues(5), vec![RedirectIssue::Loop]);
+        assert_eq!(looped.redirect_issues(1), vec![RedirectIssue::TooLong(2), RedirectIssue::Loop]);
+
+        // 打ち切った場合は長さの上限に関係なく問題とする
+        let gave_up = Fetched { response: None, hops: vec![hop("http://example.com/a")], loop_detected: false, disallowed: false, body: None };
+        assert_eq!(gave_up.redirect_issues(5), vec![RedirectIssue::TooLong(1)]);
+
+        // robots.txt で拒否された先へのリダイレクトは、そこで辿るのをやめたものとして扱う
+        let disallowed = Fetched { response: None, hops: vec![hop("http://example.com/a")], loop_detected: false, disallowed: true, body: None };
+        assert_eq!(disallowed.redirect_issues(5), vec![RedirectIssue::Disallowed]);
+    }
+
+    #[test]
+    fn test_stored_redirect_issues() {
+        let chain = |urls: &[(&str, &str)]| -> Vec<RedirectHop> {
+            urls.iter().map(|(url, location)| RedirectHop { url: url.to_string(), status: 301, location: Some(location.to_string()) }).collect()
+        };
+        let looped = chain(&[("http://example.com/a", "/b"), ("http://example.com/b", "/a#top")]);
+        let allow_all = |_: &Url| true;
+        assert_eq!(stored_redirect_issues(&looped, None, 5, allow_all), vec![RedirectIssue::Loop]);
+        let ends_in_404 = chain(&[("http://example.com/old", "/new")]);
+        assert_eq!(stored_redirect_issues(&ends_in_404, Some(404), 5, allow_all), vec![RedirectIssue::EndsInError(404)]);
+        assert!(stored_redirect_issues(&ends_in_404, Some(200), 5, allow_all).is_empty());
+        assert_eq!(stored_redirect_issues(&ends_in_404, Some(301), 5, allow_all), vec![RedirectIssue::Disallowed]);
+        assert_eq!(stored_redirect_issues(&ends_in_404, None, 5, allow_all), vec![RedirectIssue::TooLong(1)]);
+        assert_eq!(stored_redirect_issues(&ends_in_404, None, 5, |next: &Url| next.path() != "/new"), vec![RedirectIssue::Disallowed]);
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::HashSet;
+use std::error::Error;
+
+// チェック結果のエラー分類
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    NotFound,
+    ClientError,
+    ServerError,
+    Timeout,
+    Dns,
+    Connect,
+    Tls,
+    Redirect,
+    Body,
+    Request,
+}
+
+impl ErrorCategory {
+    pub const ALL: [ErrorCategory; 10] = [
+        ErrorCategory::NotFound,
+        ErrorCategory::ClientError,
+        ErrorCategory::ServerError,
+        ErrorCategory::Timeout,
+        ErrorCategory::Dns,
+        ErrorCategory::Connect,
+        ErrorCategory::Tls,
+        ErrorCategory::Redirect,
+        ErrorCategory::Body,
+        ErrorCategory::Request,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCategory::NotFound => "not_found",
+            ErrorCategory::ClientError => "client_error",
+            ErrorCategory::ServerError => "server_error",
+            ErrorCategory::Timeout => "timeout",
+            ErrorCategory::Dns => "dns",
+            ErrorCategory::Connect => "connect",
+            ErrorCategory::Tls => "tls",
+            ErrorCategory::Redirect => "redirect",
+            ErrorCategory::Body => "body",
+            ErrorCategory::Request => "request",
+        }
+    }
+
+    // as_str の逆（保存済みの pages.error_category を読むときに使う）
+    pub fn parse(name: &str) -> Option<ErrorCategory> {
+        ErrorCategory::ALL.into_iter().find(|category| category.as_str() == name)
+    }
+
+    // 2xx/3xx 以外のステータスを分類する
+    pub fn from_status(status: u16) -> Option<ErrorCategory> {
+        match status {
+            404 => Some(ErrorCategory::NotFound),
+            400..=499 => Some(ErrorCategory::ClientError),
+            500..=599 => Some(ErrorCategory::ServerError),
+            _ => None,
+        }
+    }
+
+    // reqwest のエラーを分類する。DNS と TLS はエラーの原因の文字列から判定する
+    pub fn from_reqwest(error: &reqwest::Error) -> ErrorCategory {
+        if error.is_timeout() {
+            return ErrorCategory::Timeout;
+        }
+        if error.is_redirect() {
+            return ErrorCategory::Redirect;
+        }
+        if error.is_body() || error.is_decode() {
+            return ErrorCategory::Body;
+        }
+
+        let mut source = error.source();
+        while let Some(cause) = source {
+            let message = cause.to_string().to_lowercase();
+            if message.contains("dns error") || message.contains("failed to lookup address") {
+                return ErrorCategory::Dns;
+            }
+            if message.contains("certificate") || message.contains("tls") || message.contains("ssl") {
+                return ErrorCategory::Tls;
+            }
+            source = cause.source();
+        }
+
+        if error.is_connect() {
+            ErrorCategory::Connect
+        } else {
+            ErrorCategory::Request
+        }
+    }
+
+    // 通信や本文の読み込みのエラーかどうか（Body はステータスを受け取った後でも起きる）
+    // HTTPステータスのエラーとリダイレクトのエラーは含まない
+    pub fn is_network(&self) -> bool {
+        !matches!(self, ErrorCategory::NotFound | ErrorCategory::ClientError | ErrorCategory::ServerError | ErrorCategory::Redirect)
+    }
+}
+
+// reqwest のエラーメッセージに根本原因を付け加える
+pub fn error_message(error: &reqwest::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    let mut root_cause = None;
+    while let Some(cause) = source {
+        root_cause = Some(cause);
+        source = cause.source();
+    }
+    if let Some(cause) = root_cause {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+    }
+    message
+}
+
+// 1件のURLについて見つかった問題
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub url: String,
+    pub status: Option<u16>,
+    pub category: ErrorCategory,
+    pub message: Option<String>,
+}
+
+impl Finding {
+    // 例: "404 Error: http://..." / "Network Error (dns): http://... - <message>"
+    // ステータスだけを表示するのは 4xx/5xx のエラーのみ（200 の本文の読み込みに失敗した場合などは分類とメッセージを表示する）
+    pub fn summary(&self) -> String {
+        match (self.category, self.status, &self.message) {
+            (ErrorCategory::Redirect, _, Some(message)) => format!("Redirect Error: {} - {}", self.url, message),
+            (ErrorCategory::Redirect, _, None) => format!("Redirect Error: {}", self.url),
+            (category, Some(status), _) if !category.is_network() => format!("{} Error: {}", status, self.url),
+            (category, _, Some(message)) => format!("Network Error ({}): {} - {}", category.as_str(), self.url, message),
+            (category, _, None) => format!("Network Error ({}): {}", category.as_str(), self.url),
+        }
+    }
+}
+
+// --fail-on で指定された、終了コードを非0にする分類
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FailOn {
+    categories: HashSet<ErrorCategory>,
+}
+
+impl FailOn {
+    // "404,5xx,network" のようなカンマ区切りの指定を解析する
+    pub fn parse(value: &str) -> Result<FailOn, String> {
+        let mut categories = HashSet::new();
+        for class in value.split(',').map(str::trim).filter(|class| !class.is_empty()) {
+            match class {
+                "none" => {}
+                "404" => {
+                    categories.insert(ErrorCategory::NotFound);
+                }
+                "4xx" => {
+                    categories.extend([ErrorCategory::NotFound, ErrorCategory::ClientError]);
+                }
+                "5xx" => {
+                    categories.insert(ErrorCategory::ServerError);
+                }
+                "redirect" => {
+                    categories.insert(ErrorCategory::Redirect);
+                }
+                "network" => {
+                    categories.extend(ErrorCategory::ALL.iter().filter(|category| category.is_network()));
+                }
+                "all" => {
+                    categories.extend(ErrorCategory::ALL);
+                }
+                _ => return Err(format!("unknown --fail-on class: {} (expected 404, 4xx, 5xx, redirect, network, all or none)", class)),
+            }
+        }
+        Ok(FailOn { categories })
+    }
+
+    pub fn matches(&self, finding: &Finding) -> bool {
+        self.categories.contains(&finding.category)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finding(status: Option<u16>, category: ErrorCategory) -> Finding {
+        Finding { url: "http://example.com/".to_string(), status, category, message: None }
+    }
+
+    #[test]
+    fn test_category_from_status() {
+        assert_eq!(ErrorCategory::from_status(200), None);
+        assert_eq!(ErrorCategory::from_status(301), None);
+        assert_eq!(ErrorCategory::from_status(404), Some(ErrorCategory::NotFound));
+        assert_eq!(ErrorCategory::from_status(410), Some(ErrorCategory::ClientError));
+        assert_eq!(ErrorCategory::from_status(503), Some(ErrorCategory::ServerError));
+    }
+
+    #[test]
+    fn test_fail_on() {
+        let fail_on = FailOn::parse("404,5xx").unwrap();
+        assert!(fail_on.matches(&finding(Some(404), ErrorCategory::NotFound)));
+        assert!(!fail_on.matches(&finding(Some(403), ErrorCategory::ClientError)));
+        assert!(fail_on.matches(&finding(Some(502), ErrorCategory::ServerError)));
+        assert!(!fail_on.matches(&finding(None, ErrorCategory::Dns)));
+
+        let fail_on = FailOn::parse("network").unwrap();
+        assert!(fail_on.matches(&finding(None, ErrorCategory::Timeout)));
+        assert!(fail_on.matches(&finding(Some(200), ErrorCategory::Body)));
+        assert!(!fail_on.matches(&finding(Some(404), ErrorCategory::NotFound)));
+        // リダイレクトのループや長すぎるチェーンは redirect で指定する
+        assert!(!fail_on.matches(&finding(Some(301), ErrorCategory::Redirect)));
+        assert!(FailOn::parse("redirect").unwrap().matches(&finding(Some(301), ErrorCategory::Redirect)));
+
+        assert_eq!(FailOn::parse("none").unwrap(), FailOn::default());
+        assert!(FailOn::parse("3xx").is_err());
+    }
+
+    #[test]
+    fn test_finding_summary() {
+        assert_eq!(finding(Some(404), ErrorCategory::NotFound).summary(), "404 Error: http://example.com/");
+        let mut dns = finding(None, ErrorCategory::Dns);
+        dns.message = Some("failed to lookup address".to_string());
+        assert_eq!(dns.summary(), "Network Error (dns): http://example.com/ - failed to lookup address");
+        let mut redirect = finding(Some(301), ErrorCategory::Redirect);
+        redirect.message = Some("redirect loop".to_string());
+        assert_eq!(redirect.summary(), "Redirect Error: http://example.com/ - redirect loop");
+
+        // ステータスを受け取った後の本文の読み込みのエラーは、200 のエラーとして表示しない
+        let mut body = finding(Some(200), ErrorCategory::Body);
+        body.message = Some("error decoding response body".to_string());
+        assert_eq!(body.summary(), "Network Error (body): http://example.com/ - error decoding response body");
+    }
+}
@@ -0,0 +1,247 @@
+use crate::crawler::RedirectReport;
+use crate::fetch::RedirectIssue;
+use crate::output::{xml_escape as escape, ResultRecord, Summary};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.6em; }
+h2 { font-size: 1.25em; margin-top: 2em; border-bottom: 1px solid #ccc; }
+table { border-collapse: collapse; width: 100%; margin-bottom: 1em; }
+th, td { border: 1px solid #ddd; padding: 4px 8px; text-align: left; vertical-align: top; }
+th { background: #f4f4f4; cursor: pointer; user-select: none; }
+th.sorted-asc::after { content: " \25B2"; }
+th.sorted-desc::after { content: " \25BC"; }
+td.number { text-align: right; }
+ul { margin: 0; padding-left: 1.2em; }
+.summary td:first-child { font-weight: bold; }
+.failed { color: #b00; }
+.muted { color: #777; }
+"#;
+
+// 見出しをクリックするとその列で並べ替える（数値の列は数値として比較する）
+const SCRIPT: &str = r#"
+document.querySelectorAll("table.sortable").forEach(function (table) {
+  table.querySelectorAll("th").forEach(function (th, index) {
+    th.addEventListener("click", function () {
+      var asc = !th.classList.contains("sorted-asc");
+      table.querySelectorAll("th").forEach(function (other) { other.classList.remove("sorted-asc", "sorted-desc"); });
+      th.classList.add(asc ? "sorted-asc" : "sorted-desc");
+      var body = table.tBodies[0];
+      var rows = Array.prototype.slice.call(body.rows);
+      rows.sort(function (a, b) {
+        var x = a.cells[index].getAttribute("data-sort") || a.cells[index].textContent;
+        var y = b.cells[index].getAttribute("data-sort") || b.cells[index].textContent;
+        var nx = parseFloat(x), ny = parseFloat(y);
+        var result = (!isNaN(nx) && !isNaN(ny)) ? nx - ny : x.localeCompare(y);
+        return asc ? result : -result;
+      });
+      rows.forEach(function (row) { body.appendChild(row); });
+    });
+  });
+});
+"#;
+
+// 1つのHTMLファイルにまとめたクロールのレポート（CSS と JavaScript も埋め込む）
+pub fn write_html(out: &mut dyn Write, records: &[ResultRecord], redirects: &[RedirectReport], summary: &Summary) -> io::Result<()> {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, r#"<html lang="en">"#)?;
+    writeln!(out, "<head>")?;
+    writeln!(out, r#"<meta charset="utf-8">"#)?;
+    writeln!(out, "<title>check404 report</title>")?;
+    writeln!(out, "<style>{}</style>", STYLE)?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    writeln!(out, "<h1>check404 report</h1>")?;
+
+    write_summary(out, summary)?;
+    write_failures(out, records)?;
+    write_redirects(out, redirects, records)?;
+
+    writeln!(out, "<script>{}</script>", SCRIPT)?;
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")
+}
+
+fn write_summary(out: &mut dyn Write, summary: &Summary) -> io::Result<()> {
+    writeln!(out, "<h2>Summary</h2>")?;
+    writeln!(out, r#"<table class="summary">"#)?;
+    let rows = [
+        ("Run ID", summary.run_id.to_string()),
+        ("Total URLs crawled", summary.total_urls.to_string()),
+        ("Errors found", summary.errors.to_string()),
+        ("Redirect issues", summary.redirect_issues.to_string()),
+        ("External links checked", summary.external_checked.to_string()),
+        ("Skipped by robots.txt", summary.skipped_by_robots.to_string()),
+        ("Elapsed time", format!("{:.1}s", summary.elapsed_seconds)),
+    ];
+    for (label, value) in rows {
+        writeln!(out, "<tr><td>{}</td><td>{}</td></tr>", label, escape(&value))?;
+    }
+    for report in &summary.suppressed_by_pattern {
+        let label = format!("Suppressed by pattern {}", report.pattern);
+        writeln!(out, "<tr><td>{}</td><td>{} ({} crawled)</td></tr>", escape(&label), report.suppressed, report.crawled)?;
+    }
+    if summary.failed {
+        writeln!(out, r#"<tr><td>Result</td><td class="failed">failed (--fail-on)</td></tr>"#)?;
+    }
+    if summary.interrupted {
+        writeln!(out, r#"<tr><td>Interrupted</td><td class="failed">partial results (continue with --resume {})</td></tr>"#, summary.run_id)?;
+    }
+    writeln!(out, "</table>")
+}
+
+// ステータスごと（ネットワークエラーは分類ごと）にまとめた、エラーのあったURLの表
+fn write_failures(out: &mut dyn Write, records: &[ResultRecord]) -> io::Result<()> {
+    let mut groups: BTreeMap<String, Vec<&ResultRecord>> = BTreeMap::new();
+    for record in records {
+        let Some(error) = &record.error else {
+            continue;
+        };
+        let group = match record.status {
+            Some(status) => status.to_string(),
+            None => format!("Network error ({})", error.category),
+        };
+        groups.entry(group).or_default().push(record);
+    }
+
+    writeln!(out, "<h2>Broken links</h2>")?;
+    if groups.is_empty() {
+        return writeln!(out, r#"<p class="muted">No broken links found.</p>"#);
+    }
+
+    for (group, records) in &groups {
+        writeln!(out, "<h3>{} ({})</h3>", escape(group), records.len())?;
+        writeln!(out, r#"<table class="sortable">"#)?;
+        writeln!(out, "<thead><tr><th>URL</th><th>Status</th><th>Error</th><th>Found on</th><th>Depth</th><th>Time (ms)</th></tr></thead>")?;
+        writeln!(out, "<tbody>")?;
+        for record in records {
+            let error = record.error.as_ref().map(|error| error.message.as_deref().unwrap_or(error.category)).unwrap_or("");
+            let status = record.status.map(|status| status.to_string()).unwrap_or_default();
+            let depth = record.depth.map(|depth| depth.to_string()).unwrap_or_else(|| "external".to_string());
+            writeln!(
+                out,
+                r#"<tr><td><a href="{url}">{url}</a></td><td class="number">{}</td><td>{}</td><td data-sort="{}">{}</td><td class="number">{}</td><td class="number">{}</td></tr>"#,
+                escape(&status),
+                escape(error),
+                record.referrers.len(),
+                referrer_list(record),
+                escape(&depth),
+                record.elapsed_ms,
+                url = escape(&record.url),
+            )?;
+        }
+        writeln!(out, "</tbody>")?;
+        writeln!(out, "</table>")?;
+    }
+    Ok(())
+}
+
+fn referrer_list(record: &ResultRecord) -> String {
+    if record.referrers.is_empty() {
+        return r#"<span class="muted">start URL</span>"#.to_string();
+    }
+    let items: Vec<String> = record.referrers.iter()
+        .map(|referrer| format!(
+            r#"<li><a href="{url}">{url}</a> &mdash; &ldquo;{}&rdquo; <span class="muted">(href={})</span></li>"#,
+            escape(&referrer.anchor_text),
+            escape(&referrer.href),
+            url = escape(&referrer.source_url),
+        ))
+        .collect();
+    format!("<ul>{}</ul>", items.join(""))
+}
+
+fn write_redirects(out: &mut dyn Write, redirects: &[RedirectReport], records: &[ResultRecord]) -> io::Result<()> {
+    writeln!(out, "<h2>Redirect chains</h2>")?;
+    if redirects.is_empty() {
+        return writeln!(out, r#"<p class="muted">No redirect issues found.</p>"#);
+    }
+
+    for report in redirects {
+        let issues: Vec<String> = report.issues.iter().map(RedirectIssue::describe).collect();
+        writeln!(out, r#"<h3><a href="{url}">{url}</a> &mdash; {}</h3>"#, escape(&issues.join(", ")), url = escape(&report.url))?;
+        writeln!(out, "<table>")?;
+        writeln!(out, "<thead><tr><th>Hop</th><th>Status</th><th>URL</th><th>Location</th></tr></thead>")?;
+        writeln!(out, "<tbody>")?;
+        for (index, hop) in report.hops.iter().enumerate() {
+            writeln!(
+                out,
+                r#"<tr><td class="number">{}</td><td class="number">{}</td><td>{}</td><td>{}</td></tr>"#,
+                index + 1,
+                hop.status,
+                escape(&hop.url),
+                escape(hop.location.as_deref().unwrap_or("(no Location)")),
+            )?;
+        }
+        writeln!(out, "</tbody>")?;
+        writeln!(out, "</table>")?;
+        if let Some(record) = records.iter().find(|record| record.url == report.url) {
+            writeln!(out, "<p>Found on:</p>{}", referrer_list(record))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crawler::Referrer;
+    use crate::fetch::RedirectHop;
+    use crate::output::ErrorRecord;
+
+    fn record(url: &str, status: Option<u16>, category: &'static str) -> ResultRecord {
+        ResultRecord {
+            url: url.to_string(),
+            status,
+            external: false,
+            depth: Some(1),
+            hops: 1,
+            elapsed_ms: 5,
+            attempts: 1,
+            redirect_count: 0,
+            error: Some(ErrorRecord { category, message: None, summary: String::new() }),
+            redirect_issues: Vec::new(),
+            referrers: vec![Referrer { source_url: "http://example.com/".to_string(), href: "/x".to_string(), anchor_text: "<b>Old</b>".to_string() }],
+        }
+    }
+
+    #[test]
+    fn test_html_report() {
+        let summary = Summary {
+            run_id: 1,
+            total_urls: 3,
+            errors: 2,
+            redirect_issues: 1,
+            external_checked: 0,
+            skipped_by_robots: 0,
+            suppressed_by_pattern: Vec::new(),
+            throttle_waits: 0,
+            throttle_seconds: 0.0,
+            elapsed_seconds: 2.0,
+            failed: false,
+            interrupted: false,
+        };
+        let records = vec![
+            record("http://example.com/gone", Some(404), "not_found"),
+            record("http://example.com/down", None, "connect"),
+        ];
+        let redirects = vec![RedirectReport {
+            url: "http://example.com/old".to_string(),
+            hops: vec![RedirectHop { url: "http://example.com/old".to_string(), status: 301, location: Some("/old".to_string()) }],
+            issues: vec![RedirectIssue::Loop],
+        }];
+
+        let mut out = Vec::new();
+        write_html(&mut out, &records, &redirects, &summary).unwrap();
+        let html = String::from_utf8(out).unwrap();
+
+        assert!(html.contains("<h3>404 (1)</h3>"));
+        assert!(html.contains("<h3>Network error (connect) (1)</h3>"));
+        // アンカーテキストはエスケープする
+        assert!(html.contains("&ldquo;&lt;b&gt;Old&lt;/b&gt;&rdquo;"));
+        assert!(html.contains("redirect loop"));
+        assert!(html.contains("<td>/old</td>"));
+    }
+}
@@ -1,141 +1,248 @@
-use reqwest;
-use scraper::{Html, Selector};
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::env;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::time::Instant;
-use url::Url;
-use regex::Regex;
-use rusqlite::{Connection, params};
-use chrono::Utc;
-
-async fn crawl(url: &str, visited: &mut HashSet<String>, max_depth: u32, base_url: &Url, pattern_limit: &mut HashMap<String, usize>, unique_patterns: &[Regex], conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
-    let url_without_hash = url.split('#').next().unwrap_or(url);
-    let normalized_url = normalize_url(url_without_hash);
-    
-    if visited.contains(&normalized_url) {
-        return Ok(());
-    }
-    visited.insert(normalized_url.clone());
-    println!("Crawling: {}", url_without_hash);
-
-    let response = reqwest::get(url_without_hash).await?;
-    let status = response.status().as_u16();
-
-    // SQLiteにデータを保存
-    let domain = base_url.domain().unwrap_or("").to_string();
-    let current_time = Utc::now().to_rfc3339();
-    conn.execute(
-        "INSERT INTO pages (check_url, domain, status, updated_at) VALUES (?1, ?2, ?3, ?4)",
-        params![url_without_hash, domain, status, current_time],
-    )?;
-
-    if status == 404 {
-        println!("404 Error: {}", url_without_hash);
-        return Ok(());
-    }
+use std::io::{self, BufWriter, Write};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+mod cli;
+mod config;
+mod crawler;
+mod db;
+mod diff;
+mod fetch;
+mod findings;
+mod html;
+mod normalize;
+mod output;
+mod patterns;
+mod rate_limit;
+mod report;
+mod retry;
+mod robots;
+mod storage;
+
+use cli::{Cli, Command, CrawlArgs};
+use crawler::{crawl, CrawlOptions, Crawler};
+use fetch::HttpOptions;
+use normalize::Normalizer;
+use output::{Format, Summary};
+use patterns::{UrlPattern, UrlPatterns};
+use rate_limit::RateLimiter;
+use retry::RetryPolicy;
+use robots::Robots;
+use storage::{NoopStorage, SqliteStorage, Storage};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
-    let html = response.text().await?;
-    let document = Html::parse_document(&html);
-    let selector = Selector::parse("a").unwrap();
-
-    let mut queue: VecDeque<String> = VecDeque::new();
-
-    for element in document.select(&selector) {
-        if let Some(href) = element.value().attr("href") {
-            if !href.starts_with("tel:") && !href.starts_with("mailto:") {
-                if let Ok(mut absolute_url) = Url::parse(url_without_hash)?.join(href) {
-                    absolute_url.set_fragment(None);
-                    if absolute_url.domain() == base_url.domain() {
-                        let url_str = absolute_url.to_string();
-                        let normalized_url_str = normalize_url(&url_str);
-                        let depth = normalized_url_str.matches('/').count() - 2;
-                        if depth <= max_depth as usize && !visited.contains(&normalized_url_str) {
-                            let pattern = get_url_pattern(&normalized_url_str, unique_patterns);
-                            let count = pattern_limit.entry(pattern).or_insert(0);
-                            if *count < 3 {
-                                *count += 1;
-                                queue.push_back(url_str);
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    while let Some(next_url) = queue.pop_front() {
-        let next_future = Box::pin(crawl(&next_url, visited, max_depth, base_url, pattern_limit, unique_patterns, conn));
-        next_future.await?;
+#[tokio::main]
+async fn main() -> Result<ExitCode, BoxError> {
+    match Cli::parse_from_env()?.command {
+        Command::Crawl(args) => run_crawl(*args).await,
+        Command::Report(args) => report::run(&args),
+        Command::Diff(args) => diff::run(&args),
     }
-
-    Ok(())
 }
 
-// normalize_url, get_url_pattern, load_unique_patterns 関数は変更なし
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
-
-    // ヘルプメッセージの表示部分は変更なし
+async fn run_crawl(mut args: CrawlArgs) -> Result<ExitCode, BoxError> {
+    let http_options = HttpOptions {
+        connect_timeout: Duration::from_secs_f64(args.connect_timeout),
+        read_timeout: Duration::from_secs_f64(args.read_timeout),
+        timeout: Duration::from_secs_f64(args.timeout),
+        user_agent: args.user_agent.clone().unwrap_or_else(|| fetch::DEFAULT_USER_AGENT.to_string()),
+        pool_idle_timeout: Duration::from_secs_f64(args.pool_idle_timeout),
+        pool_max_idle_per_host: args.pool_max_idle,
+    };
 
-    let start_url = if args.len() > 1 {
-        &args[1]
+    let patterns = if let Some(file_path) = &args.patterns {
+        UrlPatterns::load(file_path, args.pattern_limit)?
     } else {
-        "http://localhost/"
+        UrlPatterns::new(vec![UrlPattern::parse(r"/\d+")?], args.pattern_limit)
     };
 
-    let depth = if let Some(arg) = args.iter().find(|arg| arg.starts_with("-d=")) {
-        arg.strip_prefix("-d=").unwrap().parse().unwrap_or(3)
+    // 結果の保存先（--no-db なら保存しない）の初期化と、この実行の記録
+    let mut storage: Box<dyn Storage> = if args.no_db {
+        Box::new(NoopStorage::default())
     } else {
-        3
+        Box::new(SqliteStorage::open(&args.db)?)
+    };
+    // --resume では保存済みの実行の開始URLとフロンティア、それまでの結果を使う（辿るURLを決める設定は元の実行と同じでなければならない）
+    let (run_id, resumed) = match args.resume {
+        Some(run_id) => {
+            let resumed = storage.resume_run(run_id, &args.options_json(), args.force_resume)?;
+            args.start_urls = resumed.start_urls.iter().map(|url| cli::parse_url(url)).collect::<Result<_, _>>()?;
+            (run_id, Some(resumed))
+        }
+        None => {
+            let start_urls: Vec<&str> = args.start_urls.iter().map(|url| url.as_str()).collect();
+            (storage.start_run(&start_urls.join(" "), &args.options_json())?, None)
+        }
     };
 
-    let pattern_file = args.iter()
-        .find(|arg| arg.starts_with("-x="))
-        .map(|arg| arg.strip_prefix("-x=").unwrap());
+    let start_time = Instant::now();
+    let rate_limiter = RateLimiter::new(args.rate, Duration::from_millis(args.delay));
+
+    // 全てのリクエストで共有する HTTP クライアント
+    let client = fetch::build_client(&http_options)?;
+
+    // 開始URLのホストごとに robots.txt を読み込む
+    let mut robots = HashMap::new();
+    if !args.ignore_robots {
+        for start_url in &args.start_urls {
+            let Some(host) = start_url.host_str() else {
+                continue;
+            };
+            if robots.contains_key(host) {
+                continue;
+            }
+            let host_robots = Robots::fetch(&client, &rate_limiter, start_url, &robots::product_token(&http_options.user_agent)).await;
+            if let Some(crawl_delay) = host_robots.crawl_delay() {
+                rate_limiter.set_host_delay(start_url, crawl_delay);
+            }
+            robots.insert(host.to_string(), host_robots);
+        }
+    }
+
+    let retry = RetryPolicy { max_attempts: args.retries + 1, base_delay: Duration::from_millis(args.retry_delay), jitter: !args.no_retry_jitter };
+    let options = CrawlOptions {
+        max_depth: args.depth,
+        depth_mode: args.depth_mode,
+        normalizer: Normalizer::new(&args.normalize, &args.no_normalize),
+        check_external: args.check_external,
+        max_redirects: args.max_redirects,
+        retry,
+        include: args.include.clone(),
+        exclude: args.exclude.clone(),
+        progress_to_stderr: args.format != Format::Text,
+        run_id,
+    };
+    let crawler = Arc::new(Crawler::new(options, args.start_urls.clone(), client, patterns, storage, rate_limiter, robots));
+    if let Some(resumed) = resumed {
+        let done = resumed.frontier.iter().filter(|record| record.done).count();
+        eprintln!("Resuming run #{}: {} of {} URLs already done", run_id, done, resumed.frontier.len());
+        crawler.restore(resumed);
+    }
+    for start_url in &args.start_urls {
+        crawler.seed(start_url.as_str())?;
+    }
+
+    // 共有フロンティアを複数のワーカーで並行して処理
+    // Ctrl-C か SIGTERM を受けたら新しいURLの取り出しをやめ、処理中のURLが終わるのを待つ（2回目は処理中のものも中止する）
+    // ワーカーがエラーで終わった場合も同じように止め、それまでの結果を保存して報告してからエラーを返す
+    let mut workers = JoinSet::new();
+    for _ in 0..args.concurrency {
+        workers.spawn(crawl(Arc::clone(&crawler)));
+    }
+    let mut signals = ShutdownSignals::new()?;
+    let mut crawl_error: Option<BoxError> = None;
+    loop {
+        tokio::select! {
+            joined = workers.join_next() => match joined {
+                Some(result) => {
+                    let error = match result {
+                        Ok(Ok(())) => continue,
+                        Ok(Err(e)) => e,
+                        Err(e) => e.into(),
+                    };
+                    if crawl_error.is_none() {
+                        eprintln!("Stopping after an error: {}", error);
+                        crawler.stop();
+                        crawl_error = Some(error);
+                    }
+                }
+                None => break,
+            },
+            _ = signals.recv() => {
+                if crawler.interrupted() {
+                    eprintln!("Cancelling in-flight requests");
+                    workers.shutdown().await;
+                    break;
+                }
+                eprintln!("Interrupted: waiting for in-flight requests to finish (press Ctrl-C again to cancel them)");
+                crawler.stop();
+            }
+        }
+    }
+    let elapsed_time = start_time.elapsed();
 
-    let unique_patterns = if let Some(file_path) = pattern_file {
-        load_unique_patterns(file_path)?
+    let failed = crawler.findings_report().iter().any(|(finding, _)| args.fail_on.matches(finding));
+    let mut summary = Summary::new(&crawler, elapsed_time, failed);
+    // エラーで止めた実行は --resume できないので、中断したものとしては報告しない
+    summary.interrupted &= crawl_error.is_none();
+    let exit_status = if crawl_error.is_some() {
+        "failed"
+    } else if summary.interrupted {
+        "interrupted"
+    } else if failed {
+        "failed"
     } else {
-        vec![Regex::new(r"/\d+").unwrap()]
+        "success"
     };
+    if let Err(e) = crawler.finish_run(&summary.totals(), exit_status) {
+        crawl_error.get_or_insert(e);
+    }
 
-    let mut visited = HashSet::new();
-    let mut pattern_limit = HashMap::new();
-
-    // SQLiteデータベースの初期化
-    let conn = Connection::open("crawl_data.db")?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS pages (
-            id INTEGER PRIMARY KEY,
-            check_url TEXT NOT NULL,
-            domain TEXT NOT NULL,
-            status INTEGER NOT NULL,
-            updated_at TEXT NOT NULL
-        )",
-        [],
-    )?;
+    // --output が指定されていればファイルに、なければ標準出力に書き出す
+    match &args.output {
+        Some(path) => {
+            let mut out = BufWriter::new(File::create(path)?);
+            output::write(&mut out, args.format, &crawler, &summary, args.check_external)?;
+            out.flush()?;
+        }
+        None => output::write(&mut io::stdout().lock(), args.format, &crawler, &summary, args.check_external)?,
+    }
 
-    let start_time = Instant::now();
-    let base_url = Url::parse(start_url).unwrap();
-    crawl(start_url, &mut visited, depth, &base_url, &mut pattern_limit, &unique_patterns, &conn).await?;
-    let elapsed_time = start_time.elapsed();
+    if let Some(path) = &args.html_report {
+        let mut out = BufWriter::new(File::create(path)?);
+        html::write_html(&mut out, &output::result_records(&crawler), &crawler.redirect_report(), &summary)?;
+        out.flush()?;
+    }
 
-    println!("Total URLs crawled: {}", visited.len());
+    if let Some(e) = crawl_error {
+        return Err(e);
+    }
+    if summary.interrupted {
+        return Ok(ExitCode::from(130));
+    }
+    if failed {
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
 
-    let elapsed_seconds = elapsed_time.as_secs();
-    let hours = elapsed_seconds / 3600;
-    let minutes = (elapsed_seconds % 3600) / 60;
-    let seconds = elapsed_seconds % 60;
+// Ctrl-C（SIGINT）と SIGTERM の受信
+// ループの前に1回だけ登録し、ワーカーの終了を処理している間に届いたシグナルも取りこぼさないようにする
+struct ShutdownSignals {
+    #[cfg(unix)]
+    interrupt: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+    #[cfg(windows)]
+    ctrl_c: tokio::signal::windows::CtrlC,
+}
 
-    if hours > 0 {
-        println!("Total elapsed time: {}時間{}分{}秒", hours, minutes, seconds);
-    } else {
-        println!("Total elapsed time: {}分{}秒", minutes, seconds);
+impl ShutdownSignals {
+    fn new() -> io::Result<ShutdownSignals> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            Ok(ShutdownSignals { interrupt: signal(SignalKind::interrupt())?, terminate: signal(SignalKind::terminate())? })
+        }
+        #[cfg(windows)]
+        {
+            Ok(ShutdownSignals { ctrl_c: tokio::signal::windows::ctrl_c()? })
+        }
     }
 
-    Ok(())
-}
\ No newline at end of file
+    // 次のシグナルを受けるまで待つ
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        tokio::select! {
+            _ = self.interrupt.recv() => {}
+            _ = self.terminate.recv() => {}
+        }
+        #[cfg(windows)]
+        self.ctrl_c.recv().await;
+    }
+}
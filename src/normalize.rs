@@ -0,0 +1,216 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+// 正規化の各ステップ。同じページを指すURLを visited で1件として扱うために使う
+// （取得するのは元のURLのまま）
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NormalizeStep {
+    // スキームとホスト名を小文字にする（パスは大文字と小文字を区別する）
+    LowercaseHost,
+    // http の :80 と https の :443 を取り除く
+    DefaultPort,
+    // %7e のような16進数を大文字にし、英数字と - . _ ~ はデコードする
+    PercentEncoding,
+    // クエリパラメータを名前順に並べる
+    SortQuery,
+    // utm_* や gclid などのトラッキング用のパラメータを取り除く
+    TrackingParams,
+    // 末尾の index.html などを取り除く
+    IndexFile,
+    // パスの末尾のスラッシュを取り除く
+    TrailingSlash,
+    // パスも小文字にする（大文字と小文字を区別しないサーバー向け。デフォルトでは無効）
+    LowercasePath,
+}
+
+const DEFAULT_STEPS: [NormalizeStep; 7] = [
+    NormalizeStep::LowercaseHost,
+    NormalizeStep::DefaultPort,
+    NormalizeStep::PercentEncoding,
+    NormalizeStep::SortQuery,
+    NormalizeStep::TrackingParams,
+    NormalizeStep::IndexFile,
+    NormalizeStep::TrailingSlash,
+];
+
+const TRACKING_PARAMS: [&str; 6] = ["gclid", "fbclid", "msclkid", "mc_cid", "mc_eid", "_ga"];
+
+const INDEX_FILES: [&str; 6] = ["index.html", "index.htm", "index.php", "default.htm", "default.html", "default.aspx"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Normalizer {
+    steps: Vec<NormalizeStep>,
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Normalizer { steps: DEFAULT_STEPS.to_vec() }
+    }
+}
+
+impl Normalizer {
+    // デフォルトのステップに enable を加え、disable を除く
+    pub fn new(enable: &[NormalizeStep], disable: &[NormalizeStep]) -> Self {
+        let mut steps = DEFAULT_STEPS.to_vec();
+        steps.extend(enable.iter().filter(|step| !DEFAULT_STEPS.contains(step)));
+        steps.retain(|step| !disable.contains(step));
+        Normalizer { steps }
+    }
+
+    fn has(&self, step: NormalizeStep) -> bool {
+        self.steps.contains(&step)
+    }
+
+    pub fn normalize(&self, url: &str) -> String {
+        let url = url.split('#').next().unwrap_or(url);
+        let Some((scheme, rest)) = url.split_once("://") else {
+            return url.to_string();
+        };
+        let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+        let (authority, rest) = rest.split_at(authority_end);
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (rest, None),
+        };
+
+        let mut scheme = scheme.to_string();
+        let mut authority = authority.to_string();
+        if self.has(NormalizeStep::LowercaseHost) {
+            scheme = scheme.to_lowercase();
+            // ユーザー情報は大文字と小文字を区別する
+            let host_start = authority.rfind('@').map_or(0, |at| at + 1);
+            authority = format!("{}{}", &authority[..host_start], authority[host_start..].to_lowercase());
+        }
+        if self.has(NormalizeStep::DefaultPort) {
+            let default_port = match scheme.to_lowercase().as_str() {
+                "http" => Some(":80"),
+                "https" => Some(":443"),
+                _ => None,
+            };
+            if let Some(port) = default_port.filter(|port| authority.ends_with(port)) {
+                authority.truncate(authority.len() - port.len());
+            }
+        }
+
+        let mut path = path.to_string();
+        if self.has(NormalizeStep::PercentEncoding) {
+            path = normalize_percent_encoding(&path);
+        }
+        if self.has(NormalizeStep::LowercasePath) {
+            path = path.to_lowercase();
+        }
+        if self.has(NormalizeStep::IndexFile) {
+            let file_start = path.rfind('/').map_or(0, |slash| slash + 1);
+            if INDEX_FILES.iter().any(|index| path[file_start..].eq_ignore_ascii_case(index)) {
+                path.truncate(file_start);
+            }
+        }
+        if self.has(NormalizeStep::TrailingSlash) {
+            path = path.trim_end_matches('/').to_string();
+        }
+
+        // クエリを正規化するステップがなければ、クエリはそのまま残す
+        let query = match query {
+            Some(query) if [NormalizeStep::PercentEncoding, NormalizeStep::TrackingParams, NormalizeStep::SortQuery].iter().any(|step| self.has(*step)) => {
+                Some(self.normalize_query(query)).filter(|query| !query.is_empty())
+            }
+            query => query.map(str::to_string),
+        };
+
+        match query {
+            Some(query) => format!("{}://{}{}?{}", scheme, authority, path, query),
+            None => format!("{}://{}{}", scheme, authority, path),
+        }
+    }
+
+    fn normalize_query(&self, query: &str) -> String {
+        let mut params: Vec<String> = query.split('&').filter(|param| !param.is_empty()).map(str::to_string).collect();
+        if self.has(NormalizeStep::PercentEncoding) {
+            params = params.iter().map(|param| normalize_percent_encoding(param)).collect();
+        }
+        if self.has(NormalizeStep::TrackingParams) {
+            params.retain(|param| !is_tracking_param(param.split('=').next().unwrap_or("")));
+        }
+        if self.has(NormalizeStep::SortQuery) {
+            // 同じ名前のパラメータの順番は変えない
+            params.sort_by(|a, b| a.split('=').next().cmp(&b.split('=').next()));
+        }
+        params.join("&")
+    }
+}
+
+fn is_tracking_param(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name.starts_with("utm_") || TRACKING_PARAMS.contains(&name.as_str())
+}
+
+// RFC 3986 6.2.2.2: 予約されていない文字のエンコードはデコードし、それ以外は16進数を大文字にそろえる
+fn normalize_percent_encoding(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut normalized = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') => {
+                normalized.push(byte as char);
+                i += 3;
+            }
+            (b'%', Some(byte)) => {
+                normalized.push_str(&format!("%{:02X}", byte));
+                i += 3;
+            }
+            _ => {
+                let char_len = text[i..].chars().next().map_or(1, char::len_utf8);
+                normalized.push_str(&text[i..i + char_len]);
+                i += char_len;
+            }
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_url() {
+        let normalizer = Normalizer::default();
+        let normalize = |url| normalizer.normalize(url);
+
+        assert_eq!(normalize("http://example.com"), "http://example.com");
+        assert_eq!(normalize("http://example.com/"), "http://example.com");
+        assert_eq!(normalize("http://example.com/page/"), "http://example.com/page");
+        assert_eq!(normalize("HTTP://EXAMPLE.COM/Page#top"), "http://example.com/Page");
+        // パスの大文字と小文字は区別する
+        assert_ne!(normalize("http://example.com/Docs"), normalize("http://example.com/docs"));
+        assert_eq!(normalize("http://example.com:80/a"), "http://example.com/a");
+        assert_eq!(normalize("https://example.com:443/a"), "https://example.com/a");
+        assert_eq!(normalize("https://example.com:8443/a"), "https://example.com:8443/a");
+        assert_eq!(normalize("http://example.com/%7euser/a%2fb"), "http://example.com/~user/a%2Fb");
+        assert_eq!(normalize("http://example.com/?b=2&a=1"), normalize("http://example.com/?a=1&b=2"));
+        assert_eq!(normalize("http://example.com/a?utm_source=x&id=3&fbclid=y"), "http://example.com/a?id=3");
+        assert_eq!(normalize("http://example.com/a?utm_source=x"), "http://example.com/a");
+        assert_eq!(normalize("http://example.com/docs/index.html"), "http://example.com/docs");
+        assert_eq!(normalize("http://example.com/Index.HTM"), "http://example.com");
+        assert_eq!(normalize("http://example.com/reindex.html"), "http://example.com/reindex.html");
+        assert_eq!(normalize("http://User@EXAMPLE.com/"), "http://User@example.com");
+    }
+
+    #[test]
+    fn test_toggle_steps() {
+        let normalizer = Normalizer::new(&[NormalizeStep::LowercasePath], &[NormalizeStep::SortQuery, NormalizeStep::IndexFile, NormalizeStep::TrailingSlash]);
+        assert_eq!(normalizer.normalize("http://example.com/Docs/"), "http://example.com/docs/");
+        assert_eq!(normalizer.normalize("http://example.com/?b=2&a=1"), "http://example.com/?b=2&a=1");
+        assert_eq!(normalizer.normalize("http://example.com/index.html"), "http://example.com/index.html");
+
+        // 全て無効にすると、フラグメント以外はそのまま
+        let all: Vec<NormalizeStep> = NormalizeStep::value_variants().to_vec();
+        let normalizer = Normalizer::new(&[], &all);
+        assert_eq!(normalizer.normalize("HTTP://Example.com:80/A/%7e?b=1&a=2&#x"), "HTTP://Example.com:80/A/%7e?b=1&a=2&");
+    }
+}
@@ -0,0 +1,403 @@
+use crate::crawler::{CheckResult, Crawler, PatternReport, Referrer};
+use crate::db;
+use crate::fetch::RedirectIssue;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::time::Duration;
+
+// クロール結果の出力形式
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    // 人が読むためのレポート
+    #[default]
+    Text,
+    // results と summary を持つ1つのJSONオブジェクト
+    Json,
+    // 1行に1件の結果、最後の行に summary
+    Ndjson,
+    // チェックしたURLを1件のテストケースとする JUnit XML
+    Junit,
+}
+
+// クロール全体の集計
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Summary {
+    pub run_id: i64,
+    pub total_urls: usize,
+    pub errors: usize,
+    pub redirect_issues: usize,
+    pub external_checked: usize,
+    pub skipped_by_robots: usize,
+    // --patterns の上限で辿らなかったURLの数（パターンのグループごと）
+    pub suppressed_by_pattern: Vec<PatternReport>,
+    pub throttle_waits: u64,
+    pub throttle_seconds: f64,
+    pub elapsed_seconds: f64,
+    // --fail-on に一致するエラーがあったかどうか
+    pub failed: bool,
+    // Ctrl-C などで中断した場合は true（結果は途中までのもの）
+    pub interrupted: bool,
+}
+
+impl Summary {
+    pub fn new(crawler: &Crawler, elapsed_time: Duration, failed: bool) -> Summary {
+        let (throttle_waits, throttle_time) = crawler.throttle_stats();
+        let (total_urls, external_checked) = crawler.checked_counts();
+        Summary {
+            run_id: crawler.run_id(),
+            total_urls,
+            errors: crawler.findings_report().len(),
+            redirect_issues: crawler.redirect_report().len(),
+            external_checked,
+            skipped_by_robots: crawler.skipped_count(),
+            suppressed_by_pattern: crawler.pattern_report(),
+            throttle_waits,
+            throttle_seconds: throttle_time.as_secs_f64(),
+            elapsed_seconds: elapsed_time.as_secs_f64(),
+            failed,
+            interrupted: crawler.interrupted(),
+        }
+    }
+
+    pub fn totals(&self) -> db::RunTotals {
+        db::RunTotals {
+            total_urls: self.total_urls,
+            errors: self.errors,
+            redirect_issues: self.redirect_issues,
+            external_checked: self.external_checked,
+            skipped_by_robots: self.skipped_by_robots,
+        }
+    }
+}
+
+// JSON に出力する1件分の結果
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ResultRecord {
+    pub url: String,
+    pub status: Option<u16>,
+    pub external: bool,
+    pub depth: Option<usize>,
+    pub hops: usize,
+    pub elapsed_ms: u64,
+    pub attempts: u32,
+    pub redirect_count: usize,
+    pub error: Option<ErrorRecord>,
+    pub redirect_issues: Vec<String>,
+    pub referrers: Vec<Referrer>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ErrorRecord {
+    pub category: &'static str,
+    pub message: Option<String>,
+    // テキストのレポートと同じ1行の説明（JUnit XML の message に使う）
+    #[serde(skip)]
+    pub summary: String,
+}
+
+impl ResultRecord {
+    fn new(result: CheckResult, redirect_issues: Vec<String>, referrers: Vec<Referrer>) -> ResultRecord {
+        ResultRecord {
+            url: result.url,
+            status: result.status,
+            external: result.external,
+            depth: result.depth,
+            hops: result.hops,
+            elapsed_ms: result.elapsed.as_millis() as u64,
+            attempts: result.attempts,
+            redirect_count: result.redirect_count,
+            error: result.error.map(|finding| ErrorRecord { category: finding.category.as_str(), summary: finding.summary(), message: finding.message }),
+            redirect_issues,
+            referrers,
+        }
+    }
+}
+
+// チェックした全てのURLの結果をリンク元とリダイレクトの問題とともに集める
+pub fn result_records(crawler: &Crawler) -> Vec<ResultRecord> {
+    let mut redirect_issues: HashMap<String, Vec<String>> = crawler.redirect_report().into_iter()
+        .map(|report| (report.url, report.issues.iter().map(RedirectIssue::describe).collect()))
+        .collect();
+    crawler.results().into_iter()
+        .map(|result| {
+            let issues = redirect_issues.remove(&result.url).unwrap_or_default();
+            let referrers = crawler.referrers(&result.url);
+            ResultRecord::new(result, issues, referrers)
+        })
+        .collect()
+}
+
+pub fn write(out: &mut dyn Write, format: Format, crawler: &Crawler, summary: &Summary, check_external: bool) -> io::Result<()> {
+    match format {
+        Format::Text => write_text(out, crawler, summary, check_external),
+        Format::Json => write_json(out, &result_records(crawler), summary),
+        Format::Ndjson => write_ndjson(out, &result_records(crawler), summary),
+        Format::Junit => write_junit(out, &result_records(crawler), &crawler.skipped_urls(), summary),
+    }
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    results: &'a [ResultRecord],
+    summary: &'a Summary,
+}
+
+pub fn write_json(out: &mut dyn Write, records: &[ResultRecord], summary: &Summary) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *out, &JsonReport { results: records, summary })?;
+    writeln!(out)
+}
+
+// NDJSON の各行。"type" で結果と集計を区別する
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum NdjsonLine<'a> {
+    Result(&'a ResultRecord),
+    Summary(&'a Summary),
+}
+
+pub fn write_ndjson(out: &mut dyn Write, records: &[ResultRecord], summary: &Summary) -> io::Result<()> {
+    for record in records {
+        serde_json::to_writer(&mut *out, &NdjsonLine::Result(record))?;
+        writeln!(out)?;
+    }
+    serde_json::to_writer(&mut *out, &NdjsonLine::Summary(summary))?;
+    writeln!(out)
+}
+
+// エラーのあったURLとリダイレクトに問題のあったURLを failure とし、リンク元をその本文に書く
+// robots.txt で拒否されたURLは skipped のテストケースにする
+pub fn write_junit(out: &mut dyn Write, records: &[ResultRecord], skipped: &[String], summary: &Summary) -> io::Result<()> {
+    let failures = records.iter().filter(|record| record.error.is_some() || !record.redirect_issues.is_empty()).count();
+    let tests = records.len() + skipped.len();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<testsuites name="check404" tests="{}" failures="{}" time="{:.3}">"#, tests, failures, summary.elapsed_seconds)?;
+    writeln!(out, r#"  <testsuite name="check404" tests="{}" failures="{}" errors="0" skipped="{}" time="{:.3}">"#, tests, failures, skipped.len(), summary.elapsed_seconds)?;
+    for record in records {
+        // classname はホスト名（サイト外のリンクは external. を付ける）
+        let host = url_host(&record.url);
+        let classname = if record.external { format!("external.{}", host) } else { host };
+        let time = record.elapsed_ms as f64 / 1000.0;
+        write!(out, r#"    <testcase classname="{}" name="{}" time="{:.3}""#, xml_escape(&classname), xml_escape(&record.url), time)?;
+
+        let (failure_type, message) = match (&record.error, record.redirect_issues.is_empty()) {
+            (Some(error), _) => (error.category, error.summary.clone()),
+            (None, false) => ("redirect", format!("Redirect Issue: {} ({})", record.url, record.redirect_issues.join(", "))),
+            (None, true) => {
+                writeln!(out, "/>")?;
+                continue;
+            }
+        };
+        writeln!(out, ">")?;
+        let body: Vec<String> = record.referrers.iter()
+            .map(|referrer| format!("found on: {} (href=\"{}\", text=\"{}\")", referrer.source_url, referrer.href, referrer.anchor_text))
+            .collect();
+        writeln!(out, r#"      <failure type="{}" message="{}">{}</failure>"#, failure_type, xml_escape(&message), xml_escape(&body.join("\n")))?;
+        writeln!(out, "    </testcase>")?;
+    }
+    for url in skipped {
+        writeln!(out, r#"    <testcase classname="{}" name="{}" time="0.000">"#, xml_escape(&url_host(url)), xml_escape(url))?;
+        writeln!(out, r#"      <skipped message="robots.txt"/>"#)?;
+        writeln!(out, "    </testcase>")?;
+    }
+    writeln!(out, "  </testsuite>")?;
+    writeln!(out, "</testsuites>")
+}
+
+fn url_host(url: &str) -> String {
+    url::Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string)).unwrap_or_default()
+}
+
+// HTML のレポートでも使う
+pub(crate) fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // XML 1.0 で使えない制御文字は取り除く
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn write_text(out: &mut dyn Write, crawler: &Crawler, summary: &Summary, check_external: bool) -> io::Result<()> {
+    // 問題のあったURLをリンク元とともに表示（リダイレクトの問題は別に表示）
+    let findings_report = crawler.findings_report();
+    let redirect_report = crawler.redirect_report();
+    let redirected_urls: HashSet<&str> = redirect_report.iter().map(|report| report.url.as_str()).collect();
+    let plain_findings: Vec<_> = findings_report.iter()
+        .filter(|(finding, _)| !redirected_urls.contains(finding.url.as_str()))
+        .collect();
+    if !plain_findings.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "Error Report:")?;
+        for (finding, referrers) in &plain_findings {
+            writeln!(out, "{}", finding.summary())?;
+            for referrer in referrers {
+                writeln!(out, "  found on: {} (href=\"{}\", text=\"{}\")", referrer.source_url, referrer.href, referrer.anchor_text)?;
+            }
+        }
+    }
+
+    if !redirect_report.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "Redirect Report:")?;
+        for report in &redirect_report {
+            let descriptions: Vec<String> = report.issues.iter().map(RedirectIssue::describe).collect();
+            writeln!(out, "{} ({})", report.url, descriptions.join(", "))?;
+            for hop in &report.hops {
+                writeln!(out, "  {} {} -> {}", hop.status, hop.url, hop.location.as_deref().unwrap_or("(no Location)"))?;
+            }
+            for referrer in crawler.referrers(&report.url) {
+                writeln!(out, "  found on: {} (href=\"{}\", text=\"{}\")", referrer.source_url, referrer.href, referrer.anchor_text)?;
+            }
+        }
+    }
+
+    if !plain_findings.is_empty() || !redirect_report.is_empty() {
+        writeln!(out)?;
+    }
+
+    if summary.interrupted {
+        writeln!(out, "Interrupted: partial results (continue with --resume {})", summary.run_id)?;
+    }
+    writeln!(out, "Run ID: {}", summary.run_id)?;
+    writeln!(out, "Total URLs crawled: {}", summary.total_urls)?;
+    writeln!(out, "Errors found: {}", summary.errors)?;
+    writeln!(out, "Redirect issues: {}", summary.redirect_issues)?;
+
+    if check_external {
+        writeln!(out, "External links checked: {}", summary.external_checked)?;
+    }
+    writeln!(out, "Skipped by robots.txt: {}", summary.skipped_by_robots)?;
+    if !summary.suppressed_by_pattern.is_empty() {
+        let total: usize = summary.suppressed_by_pattern.iter().map(|report| report.suppressed).sum();
+        writeln!(out, "Suppressed by pattern limits: {}", total)?;
+        for report in &summary.suppressed_by_pattern {
+            writeln!(out, "  {}: {} suppressed ({} crawled)", report.pattern, report.suppressed, report.crawled)?;
+        }
+    }
+    writeln!(out, "Throttle waits: {} ({:.1}秒)", summary.throttle_waits, summary.throttle_seconds)?;
+
+    let elapsed_seconds = summary.elapsed_seconds as u64;
+    let hours = elapsed_seconds / 3600;
+    let minutes = (elapsed_seconds % 3600) / 60;
+    let seconds = elapsed_seconds % 60;
+
+    if hours > 0 {
+        writeln!(out, "Total elapsed time: {}時間{}分{}秒", hours, minutes, seconds)?;
+    } else {
+        writeln!(out, "Total elapsed time: {}分{}秒", minutes, seconds)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary() -> Summary {
+        Summary {
+            run_id: 1,
+            total_urls: 2,
+            errors: 1,
+            redirect_issues: 0,
+            external_checked: 0,
+            skipped_by_robots: 0,
+            suppressed_by_pattern: Vec::new(),
+            throttle_waits: 0,
+            throttle_seconds: 0.0,
+            elapsed_seconds: 1.5,
+            failed: true,
+            interrupted: false,
+        }
+    }
+
+    fn records() -> Vec<ResultRecord> {
+        vec![
+            ResultRecord {
+                url: "http://example.com/".to_string(),
+                status: Some(200),
+                external: false,
+                depth: Some(0),
+                hops: 0,
+                elapsed_ms: 12,
+                attempts: 1,
+                redirect_count: 0,
+                error: None,
+                redirect_issues: Vec::new(),
+                referrers: Vec::new(),
+            },
+            ResultRecord {
+                url: "http://example.com/gone".to_string(),
+                status: Some(404),
+                external: false,
+                depth: Some(1),
+                hops: 1,
+                elapsed_ms: 3,
+                attempts: 1,
+                redirect_count: 0,
+                error: Some(ErrorRecord { category: "not_found", message: None, summary: "404 Error: http://example.com/gone".to_string() }),
+                redirect_issues: Vec::new(),
+                referrers: vec![Referrer { source_url: "http://example.com/".to_string(), href: "/gone".to_string(), anchor_text: "Old".to_string() }],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_ndjson_output() {
+        let mut out = Vec::new();
+        write_ndjson(&mut out, &records(), &summary()).unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(out).unwrap().lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["type"], "result");
+        assert_eq!(lines[1]["status"], 404);
+        assert_eq!(lines[1]["error"]["category"], "not_found");
+        assert_eq!(lines[1]["referrers"][0]["anchor_text"], "Old");
+        assert_eq!(lines[2]["type"], "summary");
+        assert_eq!(lines[2]["errors"], 1);
+        assert_eq!(lines[2]["failed"], true);
+    }
+
+    #[test]
+    fn test_json_output() {
+        let mut out = Vec::new();
+        write_json(&mut out, &records(), &summary()).unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+        assert_eq!(report["results"].as_array().unwrap().len(), 2);
+        assert_eq!(report["results"][0]["error"], serde_json::Value::Null);
+        assert_eq!(report["summary"]["total_urls"], 2);
+    }
+
+    #[test]
+    fn test_junit_output() {
+        let mut out = Vec::new();
+        write_junit(&mut out, &records(), &["http://example.com/private".to_string()], &summary()).unwrap();
+        let xml = String::from_utf8(out).unwrap();
+
+        // robots.txt で拒否されたURLも tests に数える
+        assert!(xml.contains(r#"<testsuite name="check404" tests="3" failures="1" errors="0" skipped="1" time="1.500">"#));
+        assert!(xml.contains("<testcase classname=\"example.com\" name=\"http://example.com/private\" time=\"0.000\">\n      <skipped message=\"robots.txt\"/>"));
+        assert!(xml.contains(r#"<testcase classname="example.com" name="http://example.com/" time="0.012"/>"#));
+        assert!(xml.contains(r#"<failure type="not_found" message="404 Error: http://example.com/gone">found on: http://example.com/ (href=&quot;/gone&quot;, text=&quot;Old&quot;)</failure>"#));
+    }
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(xml_escape(r#"<a href="/?x=1&y='2'">"#), "&lt;a href=&quot;/?x=1&amp;y=&apos;2&apos;&quot;&gt;");
+        assert_eq!(xml_escape("bell\u{7}\ttab"), "bell\ttab");
+    }
+}
@@ -0,0 +1,127 @@
+use crate::BoxError;
+use regex::Regex;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+// パターンファイルの1行。一致したURLは同じグループとして、limit 件までだけクロールする
+//   news/[0-9]+ limit=10
+//   /news/(?P<id>[0-9]+)      名前付きキャプチャは {id} に置き換えたテンプレートでまとめる
+#[derive(Debug)]
+pub struct UrlPattern {
+    regex: Regex,
+    // None ならデフォルトの上限
+    limit: Option<usize>,
+}
+
+impl UrlPattern {
+    pub fn parse(line: &str) -> Result<UrlPattern, String> {
+        let line = line.trim();
+        let (pattern, limit) = match line.rsplit_once(char::is_whitespace) {
+            Some((pattern, option)) if option.starts_with("limit=") => {
+                let limit = option["limit=".len()..].parse().map_err(|_| format!("invalid limit in pattern: {}", line))?;
+                (pattern.trim_end(), Some(limit))
+            }
+            _ => (line, None),
+        };
+        let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+        Ok(UrlPattern { regex, limit })
+    }
+}
+
+pub struct UrlPatterns {
+    patterns: Vec<UrlPattern>,
+    default_limit: usize,
+}
+
+impl UrlPatterns {
+    pub fn new(patterns: Vec<UrlPattern>, default_limit: usize) -> Self {
+        UrlPatterns { patterns, default_limit }
+    }
+
+    // 空行と # で始まる行は読み飛ばす。同じ行が複数あれば最初のものだけを使う
+    pub fn load(path: &Path, default_limit: usize) -> Result<Self, BoxError> {
+        let text = fs::read_to_string(path)?;
+        let mut seen = HashSet::new();
+        let mut patterns = Vec::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || !seen.insert(line) {
+                continue;
+            }
+            patterns.push(UrlPattern::parse(line)?);
+        }
+        Ok(UrlPatterns::new(patterns, default_limit))
+    }
+
+    // URLが最初に一致したパターンのグループ名と上限。どのパターンにも一致しなければ None
+    // グループ名は、名前付きキャプチャがあれば一致した部分のテンプレート、なければパターンそのもの
+    pub fn group(&self, url: &str) -> Option<(String, usize)> {
+        self.patterns.iter().find_map(|pattern| {
+            let captures = pattern.regex.captures(url)?;
+            let limit = pattern.limit.unwrap_or(self.default_limit);
+            let mut named: Vec<_> = pattern.regex.capture_names().flatten()
+                .filter_map(|name| captures.name(name).map(|matched| (matched, name)))
+                .collect();
+            if named.is_empty() {
+                return Some((pattern.regex.as_str().to_string(), limit));
+            }
+
+            named.sort_by_key(|(matched, _)| matched.start());
+            let whole = captures.get(0).expect("group 0 is always present");
+            let mut template = String::new();
+            let mut position = whole.start();
+            for (matched, name) in named {
+                // 入れ子になったキャプチャは外側だけを置き換える
+                if matched.start() < position {
+                    continue;
+                }
+                template.push_str(&url[position..matched.start()]);
+                template.push_str(&format!("{{{}}}", name));
+                position = matched.end();
+            }
+            template.push_str(&url[position..whole.end()]);
+            Some((template, limit))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_parse_limit() {
+        let pattern = UrlPattern::parse("news/[0-9]+ limit=10").unwrap();
+        assert_eq!((pattern.regex.as_str(), pattern.limit), ("news/[0-9]+", Some(10)));
+        let pattern = UrlPattern::parse("news/[0-9]+").unwrap();
+        assert_eq!((pattern.regex.as_str(), pattern.limit), ("news/[0-9]+", None));
+        assert!(UrlPattern::parse("news/[0-9]+ limit=many").is_err());
+    }
+
+    #[test]
+    fn test_group() {
+        let patterns = UrlPatterns::new(vec![
+            UrlPattern::parse(r"/(news|blog)/(?P<id>[0-9]+) limit=10").unwrap(),
+            UrlPattern::parse(r"\?page=[0-9]+").unwrap(),
+        ], 3);
+
+        // 名前付きキャプチャの部分だけをまとめ、それ以外の部分は区別する
+        assert_eq!(patterns.group("http://example.com/news/1"), Some(("/news/{id}".to_string(), 10)));
+        assert_eq!(patterns.group("http://example.com/news/22/comments"), Some(("/news/{id}".to_string(), 10)));
+        assert_eq!(patterns.group("http://example.com/blog/3"), Some(("/blog/{id}".to_string(), 10)));
+        assert_eq!(patterns.group("http://example.com/list?page=2"), Some((r"\?page=[0-9]+".to_string(), 3)));
+        assert_eq!(patterns.group("http://example.com/about"), None);
+    }
+
+    #[test]
+    fn test_load_keeps_file_order() -> Result<(), BoxError> {
+        let mut file = tempfile::NamedTempFile::new()?;
+        writeln!(file, "# comment\n/news/[0-9]+ limit=5\n\n/[0-9]+\n/news/[0-9]+ limit=5")?;
+        let patterns = UrlPatterns::load(file.path(), 3)?;
+        assert_eq!(patterns.patterns.len(), 2);
+        assert_eq!(patterns.group("http://example.com/news/1"), Some(("/news/[0-9]+".to_string(), 5)));
+        assert_eq!(patterns.group("http://example.com/item/1"), Some(("/[0-9]+".to_string(), 3)));
+        Ok(())
+    }
+}
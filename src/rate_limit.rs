@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use url::Url;
+
+// ホストごとのトークンバケット
+struct HostBucket {
+    tokens: f64,
+    last_refill: Instant,
+    next_allowed: Instant,
+}
+
+#[derive(Default)]
+struct ThrottleStats {
+    waits: u64,
+    total_wait: Duration,
+}
+
+// 全てのリクエストが通過するホスト単位のレートリミッター
+pub struct RateLimiter {
+    requests_per_second: Option<f64>,
+    min_delay: Duration,
+    host_delays: Mutex<HashMap<String, Duration>>,
+    buckets: Mutex<HashMap<String, HostBucket>>,
+    stats: Mutex<ThrottleStats>,
+}
+
+impl RateLimiter {
+    // requests_per_second が None の場合はトークンバケットによる制限を行わない
+    pub fn new(requests_per_second: Option<f64>, min_delay: Duration) -> Self {
+        RateLimiter {
+            requests_per_second: requests_per_second.filter(|rps| *rps > 0.0),
+            min_delay,
+            host_delays: Mutex::new(HashMap::new()),
+            buckets: Mutex::new(HashMap::new()),
+            stats: Mutex::new(ThrottleStats::default()),
+        }
+    }
+
+    // robots.txt の Crawl-delay などホスト固有の最小間隔を設定する
+    pub fn set_host_delay(&self, url: &Url, delay: Duration) {
+        self.host_delays.lock().unwrap().insert(host_key(url), delay);
+    }
+
+    // リクエスト可能になるまで待機する
+    pub async fn acquire(&self, url: &Url) {
+        let wait = self.reserve(&host_key(url), Instant::now());
+        if !wait.is_zero() {
+            {
+                let mut stats = self.stats.lock().unwrap();
+                stats.waits += 1;
+                stats.total_wait += wait;
+            }
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    // 次のリクエスト枠を予約し、必要な待機時間を返す
+    fn reserve(&self, host: &str, now: Instant) -> Duration {
+        let min_delay = match self.host_delays.lock().unwrap().get(host) {
+            Some(delay) => self.min_delay.max(*delay),
+            None => self.min_delay,
+        };
+        let mut buckets = self.buckets.lock().unwrap();
+        let capacity = self.requests_per_second.map_or(1.0, |rps| rps.max(1.0));
+        let bucket = buckets.entry(host.to_string()).or_insert(HostBucket {
+            tokens: capacity,
+            last_refill: now,
+            next_allowed: now,
+        });
+
+        let mut wait = bucket.next_allowed.saturating_duration_since(now);
+
+        if let Some(rps) = self.requests_per_second {
+            let elapsed = now.saturating_duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * rps).min(capacity);
+            bucket.last_refill = now;
+            if bucket.tokens < 1.0 {
+                wait = wait.max(Duration::from_secs_f64((1.0 - bucket.tokens) / rps));
+            }
+            bucket.tokens -= 1.0;
+        }
+
+        bucket.next_allowed = now + wait + min_delay;
+        wait
+    }
+
+    // (待機回数, 合計待機時間)
+    pub fn throttle_stats(&self) -> (u64, Duration) {
+        let stats = self.stats.lock().unwrap();
+        (stats.waits, stats.total_wait)
+    }
+}
+
+fn host_key(url: &Url) -> String {
+    format!("{}:{}", url.host_str().unwrap_or(""), url.port_or_known_default().unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_min_delay_between_requests() {
+        let limiter = RateLimiter::new(None, Duration::from_millis(500));
+        let now = Instant::now();
+
+        assert_eq!(limiter.reserve("example.com:80", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("example.com:80", now), Duration::from_millis(500));
+        assert_eq!(limiter.reserve("example.com:80", now), Duration::from_millis(1000));
+
+        // 別ホストは独立して制限される
+        assert_eq!(limiter.reserve("example.org:80", now), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_host_delay_overrides_min_delay() {
+        let limiter = RateLimiter::new(None, Duration::from_millis(100));
+        limiter.set_host_delay(&Url::parse("http://example.com/").unwrap(), Duration::from_secs(2));
+        let now = Instant::now();
+
+        assert_eq!(limiter.reserve("example.com:80", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("example.com:80", now), Duration::from_secs(2));
+        assert_eq!(limiter.reserve("example.org:80", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("example.org:80", now), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new(Some(2.0), Duration::ZERO);
+        let now = Instant::now();
+
+        // バケット容量分はすぐにリクエストできる
+        assert_eq!(limiter.reserve("example.com:80", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("example.com:80", now), Duration::ZERO);
+        // 3件目はトークンが補充されるまで待つ
+        assert_eq!(limiter.reserve("example.com:80", now), Duration::from_millis(500));
+
+        // 2秒後にはバケットが満たされている
+        let later = now + Duration::from_secs(2);
+        assert_eq!(limiter.reserve("example.com:80", later), Duration::ZERO);
+    }
+}
@@ -0,0 +1,364 @@
+use crate::cli::ReportArgs;
+use crate::findings::{ErrorCategory, Finding};
+use crate::{db, BoxError};
+use clap::ValueEnum;
+use rusqlite::{params, Connection};
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+// report サブコマンドの出力形式
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    // pages の全ての行（フィルタに一致するもの）をリンク元と一緒に出力する
+    Csv,
+}
+
+// --status で指定するステータスの分類
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StatusClass {
+    // 404 のような個別のステータス
+    Code(u16),
+    // 4xx のような百の位
+    Class(u16),
+    // ステータスを受け取れなかったもの（pages.status が 0）
+    Network,
+}
+
+impl StatusClass {
+    pub fn parse(value: &str) -> Result<StatusClass, String> {
+        let value = value.trim().to_lowercase();
+        if value == "network" {
+            return Ok(StatusClass::Network);
+        }
+        if let Some(digit) = value.strip_suffix("xx").and_then(|digit| digit.parse::<u16>().ok()).filter(|digit| (1..=5).contains(digit)) {
+            return Ok(StatusClass::Class(digit));
+        }
+        match value.parse::<u16>() {
+            Ok(code) if (100..=599).contains(&code) => Ok(StatusClass::Code(code)),
+            _ => Err(format!("unknown status class: {} (expected e.g. 404, 4xx or network)", value)),
+        }
+    }
+
+    pub fn matches(&self, status: u16) -> bool {
+        match self {
+            StatusClass::Code(code) => status == *code,
+            StatusClass::Class(digit) => status / 100 == *digit,
+            StatusClass::Network => status == 0,
+        }
+    }
+}
+
+// 保存済みの1件分の結果（同じURLは最新のものだけ）
+pub(crate) struct StoredPage {
+    pub check_url: String,
+    // links.target_url と対応する、正規化したURL
+    pub normalized_url: String,
+    pub domain: String,
+    pub status: u16,
+    pub updated_at: String,
+    pub external: bool,
+    pub error_category: Option<String>,
+    pub error_message: Option<String>,
+    pub redirect_count: usize,
+    pub attempts: u32,
+    pub elapsed_ms: Option<u64>,
+}
+
+impl StoredPage {
+    // クロール中のレポートと同じ形式（Finding::summary）で表示する。ステータスを受け取れなかった行は status が 0
+    pub fn summary(&self) -> String {
+        let Some(category) = self.error_category.as_deref() else {
+            return format!("{} OK: {}", self.status, self.check_url);
+        };
+        match ErrorCategory::parse(category) {
+            Some(category) => Finding {
+                url: self.check_url.clone(),
+                status: Some(self.status).filter(|status| *status != 0),
+                category,
+                message: self.error_message.clone(),
+            }
+            .summary(),
+            None => format!("{} Error: {}", self.status, self.check_url),
+        }
+    }
+}
+
+// --status と --domain の絞り込み（指定がなければ全て）
+struct Filter<'a> {
+    statuses: &'a [StatusClass],
+    domains: &'a [String],
+}
+
+impl Filter<'_> {
+    fn matches(&self, page: &StoredPage) -> bool {
+        (self.statuses.is_empty() || self.statuses.iter().any(|class| class.matches(page.status)))
+            && (self.domains.is_empty() || self.domains.iter().any(|domain| domain.eq_ignore_ascii_case(&page.domain)))
+    }
+}
+
+// crawl_data.db に保存された結果を表示する
+pub fn run(args: &ReportArgs) -> Result<ExitCode, BoxError> {
+    let conn = db::open_existing(&args.db)?;
+    let mut out = io::stdout().lock();
+    if args.list_runs {
+        write_runs(&mut out, &db::list_runs(&conn)?)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let run_id = select_run(&conn, args.run)?;
+    let filter = Filter { statuses: &args.status, domains: &args.domain };
+    let pages: Vec<StoredPage> = latest_pages(&conn, run_id)?.into_iter().filter(|page| filter.matches(page)).collect();
+
+    match args.format {
+        ReportFormat::Text => {
+            let pages: Vec<&StoredPage> = pages.iter().filter(|page| args.all || page.error_category.is_some()).collect();
+            write_text(&mut out, &conn, run_id, &pages)?
+        }
+        ReportFormat::Csv => write_csv(&mut out, &conn, run_id, &pages)?,
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// --run を省略した場合は最新の実行（runs がない古いデータベースでは None で、全ての行の最新）
+// 存在しない実行を指定した場合は、空のレポートではなくエラーにする
+fn select_run(conn: &Connection, run: Option<i64>) -> Result<Option<i64>, BoxError> {
+    let runs = db::list_runs(conn)?;
+    match run {
+        Some(run_id) if runs.iter().any(|run| run.id == run_id) => Ok(Some(run_id)),
+        Some(run_id) => Err(format!("no run with id {}", run_id).into()),
+        None => Ok(runs.first().map(|run| run.id)),
+    }
+}
+
+fn write_runs(out: &mut dyn Write, runs: &[db::RunRow]) -> io::Result<()> {
+    for run in runs {
+        let count = |value: Option<i64>| value.map(|value| value.to_string()).unwrap_or_else(|| "-".to_string());
+        writeln!(
+            out,
+            "#{} {} [{}] urls={} errors={} redirect_issues={} {}",
+            run.id,
+            run.started_at,
+            run.exit_status,
+            count(run.total_urls),
+            count(run.errors),
+            count(run.redirect_issues),
+            run.start_urls,
+        )?;
+    }
+    Ok(())
+}
+
+fn write_text(out: &mut dyn Write, conn: &Connection, run_id: Option<i64>, pages: &[&StoredPage]) -> Result<(), BoxError> {
+    if let Some(run_id) = run_id {
+        writeln!(out, "Run ID: {}", run_id)?;
+    }
+    for page in pages {
+        writeln!(out, "{}", page.summary())?;
+        if page.error_category.is_some() {
+            for (source_url, href, anchor_text) in referrers(conn, run_id, &page.normalized_url)? {
+                writeln!(out, "  found on: {} (href=\"{}\", text=\"{}\")", source_url, href, anchor_text)?;
+            }
+        }
+    }
+
+    let errors = pages.iter().filter(|page| page.error_category.is_some()).count();
+    writeln!(out)?;
+    writeln!(out, "Errors found: {}", errors)?;
+    Ok(())
+}
+
+const CSV_HEADER: [&str; 13] = [
+    "check_url", "domain", "status", "updated_at", "external", "error_category", "error_message",
+    "redirect_count", "attempts", "elapsed_ms", "referrer_count", "referrers", "anchor_texts",
+];
+
+// リンク元が複数ある場合は " | " で区切って1つのセルに入れる
+fn write_csv(out: &mut dyn Write, conn: &Connection, run_id: Option<i64>, pages: &[StoredPage]) -> Result<(), BoxError> {
+    write_csv_row(out, &CSV_HEADER.map(str::to_string))?;
+    for page in pages {
+        let referrers = referrers(conn, run_id, &page.normalized_url)?;
+        let sources: Vec<&str> = referrers.iter().map(|(source_url, _, _)| source_url.as_str()).collect();
+        let anchor_texts: Vec<&str> = referrers.iter().map(|(_, _, anchor_text)| anchor_text.as_str()).collect();
+        write_csv_row(out, &[
+            page.check_url.clone(),
+            page.domain.clone(),
+            page.status.to_string(),
+            page.updated_at.clone(),
+            page.external.to_string(),
+            page.error_category.clone().unwrap_or_default(),
+            page.error_message.clone().unwrap_or_default(),
+            page.redirect_count.to_string(),
+            page.attempts.to_string(),
+            page.elapsed_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+            referrers.len().to_string(),
+            sources.join(" | "),
+            anchor_texts.join(" | "),
+        ])?;
+    }
+    Ok(())
+}
+
+// RFC 4180 の形式。カンマ・引用符・改行を含むセルは引用符で囲む
+fn write_csv_row(out: &mut dyn Write, cells: &[String]) -> io::Result<()> {
+    let cells: Vec<String> = cells.iter()
+        .map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.clone()
+            }
+        })
+        .collect();
+    write!(out, "{}\r\n", cells.join(","))
+}
+
+// run_id が None なら全ての実行を通して、URLごとの最新の結果
+pub(crate) fn latest_pages(conn: &Connection, run_id: Option<i64>) -> rusqlite::Result<Vec<StoredPage>> {
+    let mut stmt = conn.prepare(
+        "SELECT check_url, domain, status, updated_at, external, error_category, error_message, redirect_count, attempts, elapsed_ms, COALESCE(normalized_url, check_url) FROM pages
+         WHERE id IN (SELECT MAX(id) FROM pages WHERE ?1 IS NULL OR run_id = ?1 GROUP BY check_url)
+         ORDER BY check_url",
+    )?;
+    let pages = stmt.query_map([run_id], |row| {
+        Ok(StoredPage {
+            check_url: row.get(0)?,
+            domain: row.get(1)?,
+            status: row.get(2)?,
+            updated_at: row.get(3)?,
+            external: row.get(4)?,
+            error_category: row.get(5)?,
+            error_message: row.get(6)?,
+            redirect_count: row.get(7)?,
+            attempts: row.get(8)?,
+            elapsed_ms: row.get(9)?,
+            normalized_url: row.get(10)?,
+        })
+    })?;
+    pages.collect()
+}
+
+// target_url は正規化したURL（StoredPage::normalized_url）
+pub(crate) fn referrers(conn: &Connection, run_id: Option<i64>, target_url: &str) -> rusqlite::Result<Vec<(String, String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT source_url, href, anchor_text FROM links WHERE target_url = ?1 AND (?2 IS NULL OR run_id = ?2) ORDER BY source_url, href",
+    )?;
+    let rows = stmt.query_map(params![target_url, run_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+    rows.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{insert_links, insert_page, LinkRecord, PageRecord};
+    use crate::normalize::Normalizer;
+
+    fn test_db() -> Result<Connection, BoxError> {
+        let conn = Connection::open_in_memory()?;
+        db::init_schema(&conn)?;
+
+        let first = db::start_run(&conn, "http://example.com/", "{}")?;
+        let second = db::start_run(&conn, "http://example.com/", "{}")?;
+        let page = |check_url: &str, domain: &str, status, error_category| PageRecord { check_url: String::from(check_url), normalized_url: Normalizer::default().normalize(check_url), domain: String::from(domain), status, external: false, error_category, error_message: None, redirect_count: 0, attempts: 1, elapsed_ms: 5, hops: 1 };
+        insert_page(&conn, first, &page("http://example.com/flaky", "example.com", 503, Some("server_error")))?;
+        insert_page(&conn, second, &page("http://example.com/", "example.com", 200, None))?;
+        insert_page(&conn, second, &page("http://example.com/gone", "example.com", 404, Some("not_found")))?;
+        // 後の実行で直ったURLはエラーとして表示しない
+        insert_page(&conn, second, &page("http://example.com/flaky", "example.com", 200, None))?;
+        insert_page(&conn, second, &page("https://partner.example/down", "partner.example", 0, Some("connect")))?;
+        insert_links(&conn, first, "http://example.com/", &[LinkRecord { target_url: "http://example.com/gone".to_string(), href: "/gone-old".to_string(), anchor_text: "Old link".to_string() }])?;
+        insert_links(&conn, second, "http://example.com/", &[LinkRecord { target_url: "http://example.com/gone".to_string(), href: "/gone".to_string(), anchor_text: "Old, \"retired\" page".to_string() }])?;
+        Ok(conn)
+    }
+
+    #[test]
+    fn test_latest_pages() -> Result<(), BoxError> {
+        let conn = test_db()?;
+
+        let pages = latest_pages(&conn, None)?;
+        let errors: Vec<&str> = pages.iter().filter(|page| page.error_category.is_some()).map(|page| page.check_url.as_str()).collect();
+        assert_eq!(errors, vec!["http://example.com/gone", "https://partner.example/down"]);
+        assert_eq!(pages[2].summary(), "404 Error: http://example.com/gone");
+        assert_eq!(pages[3].summary(), "Network Error (connect): https://partner.example/down");
+        assert_eq!(pages.len(), 4);
+
+        // 実行を指定すると、その実行の結果とリンク元だけを使う
+        let first_run: Vec<String> = latest_pages(&conn, Some(1))?.iter().map(StoredPage::summary).collect();
+        assert_eq!(first_run, vec!["503 Error: http://example.com/flaky"]);
+        assert_eq!(referrers(&conn, Some(2), "http://example.com/gone")?, vec![("http://example.com/".to_string(), "/gone".to_string(), "Old, \"retired\" page".to_string())]);
+        assert_eq!(referrers(&conn, None, "http://example.com/gone")?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_run() -> Result<(), BoxError> {
+        let conn = test_db()?;
+        assert_eq!(select_run(&conn, None)?, Some(2));
+        assert_eq!(select_run(&conn, Some(1))?, Some(1));
+        assert_eq!(select_run(&conn, Some(7)).err().unwrap().to_string(), "no run with id 7");
+        Ok(())
+    }
+
+    #[test]
+    fn test_stored_summary_of_body_error() -> Result<(), BoxError> {
+        let conn = Connection::open_in_memory()?;
+        db::init_schema(&conn)?;
+        let run_id = db::start_run(&conn, "http://example.com/", "{}")?;
+        // 200 を受け取った後に本文の読み込みに失敗した行
+        insert_page(&conn, run_id, &PageRecord { check_url: "http://example.com/slow".to_string(), normalized_url: "http://example.com/slow".to_string(), domain: "example.com".to_string(), status: 200, external: false, error_category: Some("body"), error_message: Some("error decoding response body".to_string()), redirect_count: 0, attempts: 3, elapsed_ms: 5, hops: 1 })?;
+
+        let pages = latest_pages(&conn, Some(run_id))?;
+        assert_eq!(pages[0].summary(), "Network Error (body): http://example.com/slow - error decoding response body");
+        Ok(())
+    }
+
+    #[test]
+    fn test_referrers_of_url_variants() -> Result<(), BoxError> {
+        let conn = test_db()?;
+        let run_id = db::start_run(&conn, "http://example.com/", "{}")?;
+        let normalizer = Normalizer::default();
+        // 最初にキューに入った表記とは別の表記で書かれたリンクも、同じページのリンク元として引ける
+        insert_page(&conn, run_id, &PageRecord { check_url: "http://example.com/docs/".to_string(), normalized_url: normalizer.normalize("http://example.com/docs/"), domain: "example.com".to_string(), status: 404, external: false, error_category: Some("not_found"), error_message: None, redirect_count: 0, attempts: 1, elapsed_ms: 5, hops: 1 })?;
+        insert_links(&conn, run_id, "http://example.com/", &[
+            LinkRecord { target_url: normalizer.normalize("http://example.com/docs/"), href: "/docs/".to_string(), anchor_text: "Docs".to_string() },
+            LinkRecord { target_url: normalizer.normalize("http://example.com/docs/index.html?utm_source=x"), href: "/docs/index.html?utm_source=x".to_string(), anchor_text: "Docs".to_string() },
+        ])?;
+
+        let pages = latest_pages(&conn, Some(run_id))?;
+        let hrefs: Vec<String> = referrers(&conn, Some(run_id), &pages[0].normalized_url)?.into_iter().map(|(_, href, _)| href).collect();
+        assert_eq!(hrefs, vec!["/docs/", "/docs/index.html?utm_source=x"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_status_class() {
+        assert_eq!(StatusClass::parse("404"), Ok(StatusClass::Code(404)));
+        assert_eq!(StatusClass::parse("4XX"), Ok(StatusClass::Class(4)));
+        assert_eq!(StatusClass::parse("network"), Ok(StatusClass::Network));
+        assert!(StatusClass::parse("9xx").is_err());
+        assert!(StatusClass::parse("abc").is_err());
+
+        assert!(StatusClass::Class(5).matches(503));
+        assert!(!StatusClass::Class(5).matches(404));
+        assert!(StatusClass::Network.matches(0));
+    }
+
+    #[test]
+    fn test_csv_with_filters() -> Result<(), BoxError> {
+        let conn = test_db()?;
+        let statuses = [StatusClass::Class(4), StatusClass::Network];
+        let domains = ["example.com".to_string()];
+        let filter = Filter { statuses: &statuses, domains: &domains };
+        let pages: Vec<StoredPage> = latest_pages(&conn, Some(2))?.into_iter().filter(|page| filter.matches(page)).collect();
+
+        let mut out = Vec::new();
+        write_csv(&mut out, &conn, Some(2), &pages)?;
+        let csv = String::from_utf8(out)?;
+        let lines: Vec<&str> = csv.split("\r\n").collect();
+        assert_eq!(lines[0], CSV_HEADER.join(","));
+        assert_eq!(lines[1], "http://example.com/gone,example.com,404,".to_string() + &pages[0].updated_at + ",false,not_found,,0,1,5,1,http://example.com/,\"Old, \"\"retired\"\" page\"");
+        assert_eq!(lines.len(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+// Retry-After がこれより長い場合は切り詰める
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+// 一時的な失敗に対する再試行の方針
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub jitter: bool,
+}
+
+impl RetryPolicy {
+    // 429 と 5xx は再試行する
+    pub fn should_retry_status(&self, status: u16) -> bool {
+        status == 429 || (500..=599).contains(&status)
+    }
+
+    // タイムアウトと接続エラー、本文を読む途中で切れた接続は再試行する
+    // 壊れた gzip などのデコードのエラーは何度取得しても同じなので再試行しない
+    pub fn should_retry_error(&self, error: &reqwest::Error) -> bool {
+        error.is_timeout() || error.is_connect() || error.is_body()
+    }
+
+    // attempt 回目の失敗の後に待つ時間。Retry-After があればそちらを優先する
+    pub fn delay(&self, attempt: u32, headers: Option<&HeaderMap>) -> Duration {
+        if let Some(retry_after) = headers.and_then(parse_retry_after) {
+            return retry_after.min(MAX_DELAY);
+        }
+
+        let backoff = self.base_delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1))).min(MAX_DELAY);
+        if self.jitter {
+            // 半分は固定、残り半分をランダムにする
+            let half = backoff / 2;
+            half + half.mul_f64(random_fraction())
+        } else {
+            backoff
+        }
+    }
+}
+
+// Retry-After は秒数か HTTP-date のどちらか
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&Utc) - Utc::now()).to_std().unwrap_or(Duration::ZERO))
+}
+
+// 0.0〜1.0 の乱数（ジッター用なので RandomState のハッシュで十分）
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos());
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn policy(jitter: bool) -> RetryPolicy {
+        RetryPolicy { max_attempts: 4, base_delay: Duration::from_millis(100), jitter }
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let policy = policy(false);
+        assert_eq!(policy.delay(1, None), Duration::from_millis(100));
+        assert_eq!(policy.delay(2, None), Duration::from_millis(200));
+        assert_eq!(policy.delay(3, None), Duration::from_millis(400));
+        assert_eq!(policy.delay(30, None), MAX_DELAY);
+    }
+
+    #[test]
+    fn test_jitter_stays_within_range() {
+        let policy = policy(true);
+        for _ in 0..100 {
+            let delay = policy.delay(3, None);
+            assert!(delay >= Duration::from_millis(200) && delay <= Duration::from_millis(400));
+        }
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let policy = policy(true);
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("3"));
+        assert_eq!(policy.delay(1, Some(&headers)), Duration::from_secs(3));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"));
+        assert_eq!(policy.delay(1, Some(&headers)), Duration::ZERO);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("3600"));
+        assert_eq!(policy.delay(1, Some(&headers)), MAX_DELAY);
+    }
+
+    #[test]
+    fn test_retryable_statuses() {
+        let policy = policy(false);
+        assert!(policy.should_retry_status(429));
+        assert!(policy.should_retry_status(503));
+        assert!(!policy.should_retry_status(404));
+        assert!(!policy.should_retry_status(200));
+    }
+}
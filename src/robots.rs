@@ -0,0 +1,238 @@
+use crate::fetch;
+use crate::rate_limit::RateLimiter;
+use regex::Regex;
+use reqwest::{Client, Method};
+use std::time::Duration;
+use url::Url;
+
+struct Rule {
+    allow: bool,
+    pattern: String,
+    regex: Regex,
+}
+
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+// robots.txt のうち、このクローラーに適用されるグループ
+#[derive(Default)]
+pub struct Robots {
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    // 開始ホストの /robots.txt を取得する。見つからない場合や取得できない場合は全て許可、
+    // サーバーエラー（5xx）の場合は RFC 9309 に従って全て拒否として扱う
+    // product_token は User-agent 行と照合する名前（product_token 関数で求める）
+    pub async fn fetch(client: &Client, rate_limiter: &RateLimiter, base_url: &Url, product_token: &str) -> Robots {
+        let robots_url = match base_url.join("/robots.txt") {
+            Ok(url) => url,
+            Err(_) => return Robots::default(),
+        };
+
+        match fetch::fetch(client, Method::GET, &robots_url, rate_limiter, |_| true).await {
+            Ok(fetch::Fetched { response: Some(response), .. }) if response.status().is_success() => match response.text().await {
+                Ok(body) => Robots::parse(&body, product_token),
+                Err(_) => Robots::default(),
+            },
+            Ok(fetch::Fetched { response: Some(response), .. }) if response.status().is_server_error() => {
+                eprintln!("{} returned {}, treating every URL on this host as disallowed (use --ignore-robots to crawl anyway)", robots_url, response.status().as_u16());
+                Robots::disallow_all()
+            }
+            Ok(_) => Robots::default(),
+            Err(e) => {
+                eprintln!("Failed to fetch {}: {}", robots_url, e);
+                Robots::default()
+            }
+        }
+    }
+
+    pub fn parse(body: &str, product_token: &str) -> Robots {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut in_agent_lines = false;
+
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let key = key.trim().to_lowercase();
+            let value = value.trim();
+
+            if key == "user-agent" {
+                // 連続する User-agent 行は同じグループにまとめる
+                if !in_agent_lines {
+                    groups.push(Group { agents: Vec::new(), rules: Vec::new(), crawl_delay: None });
+                }
+                groups.last_mut().unwrap().agents.push(value.to_lowercase());
+                in_agent_lines = true;
+                continue;
+            }
+            in_agent_lines = false;
+
+            let Some(group) = groups.last_mut() else {
+                continue;
+            };
+            match key.as_str() {
+                "allow" | "disallow" if !value.is_empty() => {
+                    group.rules.push(Rule {
+                        allow: key == "allow",
+                        pattern: value.to_string(),
+                        regex: pattern_to_regex(value),
+                    });
+                }
+                "crawl-delay" => {
+                    group.crawl_delay = value.parse::<f64>().ok()
+                        .filter(|secs| *secs >= 0.0)
+                        .map(Duration::from_secs_f64);
+                }
+                _ => {}
+            }
+        }
+
+        // 自分の名前に一致するグループを優先し、なければ * のグループを使う
+        // 名前は大文字小文字を区別せずに全体で比べる（User-agent: c のような部分一致は採用しない）
+        let product_token = product_token.to_lowercase();
+        let specific = groups.iter().position(|group| group.agents.iter().any(|agent| !product_token.is_empty() && agent_name(agent) == product_token));
+        let wildcard = groups.iter().position(|group| group.agents.iter().any(|agent| agent == "*"));
+
+        match specific.or(wildcard) {
+            Some(index) => {
+                let group = groups.swap_remove(index);
+                Robots { rules: group.rules, crawl_delay: group.crawl_delay }
+            }
+            None => Robots::default(),
+        }
+    }
+
+    // robots.txt がサーバーエラーを返した場合
+    fn disallow_all() -> Robots {
+        Robots { rules: vec![Rule { allow: false, pattern: "/".to_string(), regex: pattern_to_regex("/") }], crawl_delay: None }
+    }
+
+    // 最も長く一致したルールを採用する。同じ長さなら Allow を優先
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+
+        let mut best: Option<&Rule> = None;
+        for rule in &self.rules {
+            if !rule.regex.is_match(&path) {
+                continue;
+            }
+            best = match best {
+                Some(current) if current.pattern.len() > rule.pattern.len() => Some(current),
+                Some(current) if current.pattern.len() == rule.pattern.len() && current.allow => Some(current),
+                _ => Some(rule),
+            };
+        }
+
+        best.is_none_or(|rule| rule.allow)
+    }
+
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+}
+
+// User-Agent ヘッダーの最初の製品名（check404/0.1.0 なら check404）。robots.txt の User-agent 行と照合する
+pub fn product_token(user_agent: &str) -> String {
+    user_agent.split_whitespace().next().unwrap_or("").split('/').next().unwrap_or("").to_lowercase()
+}
+
+// User-agent 行の値から、バージョンを除いた名前
+fn agent_name(agent: &str) -> &str {
+    agent.split('/').next().unwrap_or("").trim()
+}
+
+// "*" は任意の文字列、末尾の "$" はパスの終端を表す
+fn pattern_to_regex(pattern: &str) -> Regex {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(stripped) => (stripped, true),
+        None => (pattern, false),
+    };
+    let escaped: Vec<String> = pattern.split('*').map(regex::escape).collect();
+    let mut source = format!("^{}", escaped.join(".*"));
+    if anchored {
+        source.push('$');
+    }
+    Regex::new(&source).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed(robots: &Robots, url: &str) -> bool {
+        robots.is_allowed(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn test_disallow_and_allow() {
+        let robots = Robots::parse(
+            "User-agent: *\n\
+             Disallow: /private/\n\
+             Allow: /private/public.html\n\
+             Disallow: /*.pdf$\n\
+             Crawl-delay: 2\n",
+            "check404",
+        );
+
+        assert!(allowed(&robots, "http://example.com/"));
+        assert!(!allowed(&robots, "http://example.com/private/secret.html"));
+        assert!(allowed(&robots, "http://example.com/private/public.html"));
+        assert!(!allowed(&robots, "http://example.com/docs/manual.pdf"));
+        assert!(allowed(&robots, "http://example.com/docs/manual.pdf?download=1"));
+        assert_eq!(robots.crawl_delay(), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_specific_user_agent_group() {
+        let robots = Robots::parse(
+            "User-agent: *\n\
+             Disallow: /\n\
+             \n\
+             User-agent: googlebot\n\
+             User-agent: check404\n\
+             Disallow: /admin\n",
+            "check404",
+        );
+
+        assert!(allowed(&robots, "http://example.com/news/1"));
+        assert!(!allowed(&robots, "http://example.com/admin/login"));
+        assert_eq!(robots.crawl_delay(), None);
+    }
+
+    #[test]
+    fn test_user_agent_matching() {
+        let body = "User-agent: *\nDisallow: /\n\nUser-agent: c\nUser-agent:\nDisallow: /admin\n\nUser-agent: MyBot/2.0\nDisallow: /mybot\n";
+
+        // 名前の一部や空の User-agent には一致しない
+        let robots = Robots::parse(body, &product_token(crate::fetch::DEFAULT_USER_AGENT));
+        assert!(!allowed(&robots, "http://example.com/news/1"));
+
+        // --user-agent の製品名を大文字小文字を区別せずに使う
+        let robots = Robots::parse(body, &product_token("mybot/1.0 (+https://example.com/bot)"));
+        assert!(allowed(&robots, "http://example.com/news/1"));
+        assert!(!allowed(&robots, "http://example.com/mybot/x"));
+        assert_eq!(product_token("check404/0.1.0"), "check404");
+    }
+
+    #[test]
+    fn test_disallow_all() {
+        assert!(!allowed(&Robots::disallow_all(), "http://example.com/"));
+    }
+
+    #[test]
+    fn test_empty_disallow_allows_everything() {
+        let robots = Robots::parse("User-agent: *\nDisallow:\n", "check404");
+        assert!(allowed(&robots, "http://example.com/anything"));
+    }
+}
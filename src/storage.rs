@@ -0,0 +1,416 @@
+use crate::db::{self, FrontierRecord, LinkRecord, PageRecord, RedirectRecord, RunTotals};
+use crate::BoxError;
+use rusqlite::Connection;
+#[cfg(test)]
+use std::collections::HashSet;
+use std::path::Path;
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// クローラーが結果を書き込む先
+// 実行ごとに start_run で run_id を受け取り、以降の書き込みはその run_id で行う
+pub trait Storage: Send {
+    fn start_run(&mut self, start_urls: &str, options: &str) -> Result<i64, BoxError>;
+    fn finish_run(&mut self, run_id: i64, totals: &RunTotals, exit_status: &str) -> Result<(), BoxError>;
+    fn insert_page(&mut self, run_id: i64, page: PageRecord) -> Result<(), BoxError>;
+    fn insert_links(&mut self, run_id: i64, source_url: &str, links: Vec<LinkRecord>) -> Result<(), BoxError>;
+    fn insert_redirects(&mut self, run_id: i64, check_url: &str, hops: Vec<RedirectRecord>) -> Result<(), BoxError>;
+    // visited に入れたURLを保存する
+    fn insert_frontier(&mut self, run_id: i64, records: Vec<FrontierRecord>) -> Result<(), BoxError>;
+    // ページのチェックと、そこから見つかったURLの insert_frontier が終わったことを保存する
+    fn mark_done(&mut self, run_id: i64, url: &str) -> Result<(), BoxError>;
+    // 途中で終わった実行を実行中に戻し、開始URLと保存済みのフロンティア、それまでの結果を返す
+    // options は今回のコマンドラインの設定（runs.options と同じ形式）で、辿るURLを決める設定が違えばエラーにする
+    // allow_running が false なら、running のままの実行（別のプロセスがクロール中かもしれない）はエラーにする
+    fn resume_run(&mut self, run_id: i64, options: &str, allow_running: bool) -> Result<ResumedRun, BoxError>;
+}
+
+// --resume で続きから実行する実行
+pub struct ResumedRun {
+    pub start_urls: Vec<String>,
+    pub frontier: Vec<FrontierRecord>,
+    pub pages: Vec<PageRecord>,
+    // (リンク元のURL, リンク)
+    pub links: Vec<(String, LinkRecord)>,
+    // (check_url, リダイレクトチェーン)
+    pub redirects: Vec<(String, Vec<RedirectRecord>)>,
+}
+
+// 続きから実行するときに、元の実行と同じでなければならない runs.options の項目（フロンティアの作り方を決めるもの）
+const FRONTIER_OPTIONS: [&str; 10] = ["depth", "depth_mode", "normalize", "no_normalize", "patterns", "pattern_limit", "include", "exclude", "check_external", "ignore_robots"];
+
+// 最後まで終わった実行と、辿るURLを決める設定が違う実行は続きから実行できない
+// running の実行は、別のプロセスと同じフロンティアに書き込まないよう allow_running（--force-resume）が必要
+// 古い実行の runs.options にない項目は比べない
+fn check_resumable(run_id: i64, exit_status: &str, stored_options: &str, options: &str, allow_running: bool) -> Result<(), BoxError> {
+    match exit_status {
+        "interrupted" => {}
+        "running" if allow_running => {}
+        "running" => {
+            return Err(format!("run #{} is still marked running and another check404 may be crawling it (if that process was killed, pass --force-resume)", run_id).into());
+        }
+        _ => return Err(format!("run #{} already finished ({}), there is nothing to resume", run_id, exit_status).into()),
+    }
+    let stored: serde_json::Value = serde_json::from_str(stored_options)?;
+    let current: serde_json::Value = serde_json::from_str(options)?;
+    let mismatches: Vec<String> = FRONTIER_OPTIONS.iter()
+        .filter_map(|key| {
+            let (stored, current) = (stored.get(key)?, current.get(key)?);
+            (stored != current).then(|| format!("{} (run #{}: {}, now: {})", key, run_id, stored, current))
+        })
+        .collect();
+    if !mismatches.is_empty() {
+        return Err(format!("run #{} was started with different options, pass the same ones to resume it: {}", run_id, mismatches.join(", ")).into());
+    }
+    Ok(())
+}
+
+// この件数の書き込みが溜まるか、前回から FLUSH_INTERVAL が過ぎたらまとめて書き込む
+const BATCH_SIZE: usize = 100;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+// まだデータベースに書き込んでいない1回分の insert_*
+enum PendingWrite {
+    Page(i64, PageRecord),
+    Links(i64, String, Vec<LinkRecord>),
+    Redirects(i64, String, Vec<RedirectRecord>),
+    Frontier(i64, Vec<FrontierRecord>),
+    Done(i64, String),
+}
+
+// SQLite のデータベースファイルに保存する（デフォルト）
+// 書き込みはバッファに溜めて1つのトランザクションで保存するので、異常終了しても失うのは最後のバッチだけ
+pub struct SqliteStorage {
+    conn: Connection,
+    pending: Vec<PendingWrite>,
+    last_flush: Instant,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> Result<Self, BoxError> {
+        Ok(Self::new(db::open(path)?))
+    }
+
+    fn new(conn: Connection) -> Self {
+        SqliteStorage { conn, pending: Vec::new(), last_flush: Instant::now() }
+    }
+
+    fn push(&mut self, write: PendingWrite) -> Result<(), BoxError> {
+        self.pending.push(write);
+        if self.pending.len() >= BATCH_SIZE || self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    // 溜まっている書き込みを1つのトランザクションで保存する
+    pub fn flush(&mut self) -> Result<(), BoxError> {
+        self.last_flush = Instant::now();
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let tx = self.conn.transaction()?;
+        for write in self.pending.drain(..) {
+            match write {
+                PendingWrite::Page(run_id, page) => db::insert_page(&tx, run_id, &page)?,
+                PendingWrite::Links(run_id, source_url, links) => db::insert_links(&tx, run_id, &source_url, &links)?,
+                PendingWrite::Redirects(run_id, check_url, hops) => db::insert_redirects(&tx, run_id, &check_url, &hops)?,
+                PendingWrite::Frontier(run_id, records) => db::insert_frontier(&tx, run_id, &records)?,
+                PendingWrite::Done(run_id, url) => db::mark_done(&tx, run_id, &url)?,
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn start_run(&mut self, start_urls: &str, options: &str) -> Result<i64, BoxError> {
+        self.flush()?;
+        Ok(db::start_run(&self.conn, start_urls, options)?)
+    }
+
+    // 実行の結果を書き込む前に、残っているページをすべて保存する
+    fn finish_run(&mut self, run_id: i64, totals: &RunTotals, exit_status: &str) -> Result<(), BoxError> {
+        self.flush()?;
+        Ok(db::finish_run(&self.conn, run_id, totals, exit_status)?)
+    }
+
+    fn insert_page(&mut self, run_id: i64, page: PageRecord) -> Result<(), BoxError> {
+        self.push(PendingWrite::Page(run_id, page))
+    }
+
+    fn insert_links(&mut self, run_id: i64, source_url: &str, links: Vec<LinkRecord>) -> Result<(), BoxError> {
+        self.push(PendingWrite::Links(run_id, source_url.to_string(), links))
+    }
+
+    fn insert_redirects(&mut self, run_id: i64, check_url: &str, hops: Vec<RedirectRecord>) -> Result<(), BoxError> {
+        self.push(PendingWrite::Redirects(run_id, check_url.to_string(), hops))
+    }
+
+    fn insert_frontier(&mut self, run_id: i64, records: Vec<FrontierRecord>) -> Result<(), BoxError> {
+        self.push(PendingWrite::Frontier(run_id, records))
+    }
+
+    fn mark_done(&mut self, run_id: i64, url: &str) -> Result<(), BoxError> {
+        self.push(PendingWrite::Done(run_id, url.to_string()))
+    }
+
+    fn resume_run(&mut self, run_id: i64, options: &str, allow_running: bool) -> Result<ResumedRun, BoxError> {
+        self.flush()?;
+        let run = db::list_runs(&self.conn)?.into_iter().find(|run| run.id == run_id).ok_or(format!("no run with id {}", run_id))?;
+        check_resumable(run_id, &run.exit_status, &run.options, options, allow_running)?;
+        let tx = self.conn.transaction()?;
+        db::discard_unfinished(&tx, run_id)?;
+        db::reopen_run(&tx, run_id)?;
+        tx.commit()?;
+        Ok(ResumedRun {
+            start_urls: run.start_urls.split_whitespace().map(str::to_string).collect(),
+            frontier: db::load_frontier(&self.conn, run_id)?,
+            pages: db::load_pages(&self.conn, run_id)?,
+            links: db::load_links(&self.conn, run_id)?,
+            redirects: db::load_redirects(&self.conn, run_id)?,
+        })
+    }
+}
+
+// エラーで途中終了した場合も、溜まっている書き込みを保存する
+impl Drop for SqliteStorage {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            eprintln!("Failed to save results: {}", e);
+        }
+    }
+}
+
+// 何も保存しない（--no-db 用）。結果はクローラーが持つものだけを報告に使う
+#[derive(Default)]
+pub struct NoopStorage {
+    runs: i64,
+}
+
+impl Storage for NoopStorage {
+    fn start_run(&mut self, _start_urls: &str, _options: &str) -> Result<i64, BoxError> {
+        self.runs += 1;
+        Ok(self.runs)
+    }
+
+    fn finish_run(&mut self, _run_id: i64, _totals: &RunTotals, _exit_status: &str) -> Result<(), BoxError> {
+        Ok(())
+    }
+
+    fn insert_page(&mut self, _run_id: i64, _page: PageRecord) -> Result<(), BoxError> {
+        Ok(())
+    }
+
+    fn insert_links(&mut self, _run_id: i64, _source_url: &str, _links: Vec<LinkRecord>) -> Result<(), BoxError> {
+        Ok(())
+    }
+
+    fn insert_redirects(&mut self, _run_id: i64, _check_url: &str, _hops: Vec<RedirectRecord>) -> Result<(), BoxError> {
+        Ok(())
+    }
+
+    fn insert_frontier(&mut self, _run_id: i64, _records: Vec<FrontierRecord>) -> Result<(), BoxError> {
+        Ok(())
+    }
+
+    fn mark_done(&mut self, _run_id: i64, _url: &str) -> Result<(), BoxError> {
+        Ok(())
+    }
+
+    fn resume_run(&mut self, run_id: i64, _options: &str, _allow_running: bool) -> Result<ResumedRun, BoxError> {
+        Err(format!("run #{} was not saved (--no-db), there is nothing to resume", run_id).into())
+    }
+}
+
+// メモリ上にだけ保存する（テスト用）
+// clone したものは同じデータを共有するので、クローラーに渡した後でも中身を確認できる
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct MemoryStorage {
+    data: Arc<Mutex<MemoryData>>,
+}
+
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemoryData {
+    // (run_id, start_urls, options, exit_status)
+    pub runs: Vec<(i64, String, String, String)>,
+    pub pages: Vec<(i64, PageRecord)>,
+    // (run_id, source_url, リンク)
+    pub links: Vec<(i64, String, LinkRecord)>,
+    // (run_id, check_url, リダイレクトチェーン)
+    pub redirects: Vec<(i64, String, Vec<RedirectRecord>)>,
+    pub frontier: Vec<(i64, FrontierRecord)>,
+}
+
+#[cfg(test)]
+impl MemoryStorage {
+    pub fn data(&self) -> std::sync::MutexGuard<'_, MemoryData> {
+        self.data.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Storage for MemoryStorage {
+    fn start_run(&mut self, start_urls: &str, options: &str) -> Result<i64, BoxError> {
+        let mut data = self.data();
+        let run_id = data.runs.len() as i64 + 1;
+        data.runs.push((run_id, start_urls.to_string(), options.to_string(), "running".to_string()));
+        Ok(run_id)
+    }
+
+    fn finish_run(&mut self, run_id: i64, _totals: &RunTotals, exit_status: &str) -> Result<(), BoxError> {
+        let mut data = self.data();
+        let run = data.runs.iter_mut().find(|(id, _, _, _)| *id == run_id).ok_or(format!("no run with id {}", run_id))?;
+        run.3 = exit_status.to_string();
+        Ok(())
+    }
+
+    fn insert_page(&mut self, run_id: i64, page: PageRecord) -> Result<(), BoxError> {
+        self.data().pages.push((run_id, page));
+        Ok(())
+    }
+
+    fn insert_links(&mut self, run_id: i64, source_url: &str, links: Vec<LinkRecord>) -> Result<(), BoxError> {
+        let mut data = self.data();
+        data.links.extend(links.into_iter().map(|link| (run_id, source_url.to_string(), link)));
+        Ok(())
+    }
+
+    fn insert_redirects(&mut self, run_id: i64, check_url: &str, hops: Vec<RedirectRecord>) -> Result<(), BoxError> {
+        self.data().redirects.push((run_id, check_url.to_string(), hops));
+        Ok(())
+    }
+
+    fn insert_frontier(&mut self, run_id: i64, records: Vec<FrontierRecord>) -> Result<(), BoxError> {
+        self.data().frontier.extend(records.into_iter().map(|record| (run_id, record)));
+        Ok(())
+    }
+
+    fn mark_done(&mut self, run_id: i64, url: &str) -> Result<(), BoxError> {
+        for (id, record) in self.data().frontier.iter_mut() {
+            if *id == run_id && record.url == url {
+                record.done = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn resume_run(&mut self, run_id: i64, options: &str, allow_running: bool) -> Result<ResumedRun, BoxError> {
+        let mut data = self.data();
+        let run = data.runs.iter_mut().find(|(id, _, _, _)| *id == run_id).ok_or(format!("no run with id {}", run_id))?;
+        check_resumable(run_id, &run.3, &run.2, options, allow_running)?;
+        run.3 = "running".to_string();
+        let start_urls = run.1.split_whitespace().map(str::to_string).collect();
+        let unfinished: HashSet<String> = data.frontier.iter().filter(|(id, record)| *id == run_id && !record.done).map(|(_, record)| record.url.clone()).collect();
+        data.pages.retain(|(id, page)| *id != run_id || !unfinished.contains(&page.check_url));
+        data.links.retain(|(id, source_url, _)| *id != run_id || !unfinished.contains(source_url));
+        data.redirects.retain(|(id, check_url, _)| *id != run_id || !unfinished.contains(check_url));
+        let frontier = data.frontier.iter().filter(|(id, _)| *id == run_id).map(|(_, record)| record.clone()).collect();
+        let pages = data.pages.iter().filter(|(id, _)| *id == run_id).map(|(_, page)| page.clone()).collect();
+        let links = data.links.iter().filter(|(id, _, _)| *id == run_id).map(|(_, source_url, link)| (source_url.clone(), link.clone())).collect();
+        let redirects = data.redirects.iter().filter(|(id, _, _)| *id == run_id).map(|(_, check_url, hops)| (check_url.clone(), hops.clone())).collect();
+        Ok(ResumedRun { start_urls, frontier, pages, links, redirects })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::FrontierKind;
+
+    #[test]
+    fn test_memory_storage() -> Result<(), BoxError> {
+        let memory = MemoryStorage::default();
+        let mut storage: Box<dyn Storage> = Box::new(memory.clone());
+
+        let run_id = storage.start_run("http://example.com/", "{}")?;
+        storage.insert_page(run_id, PageRecord { check_url: "http://example.com/gone".to_string(), normalized_url: "http://example.com/gone".to_string(), domain: "example.com".to_string(), status: 404, external: false, error_category: Some("not_found"), error_message: None, redirect_count: 0, attempts: 1, elapsed_ms: 3, hops: 1 })?;
+        storage.insert_links(run_id, "http://example.com/", vec![LinkRecord { target_url: "http://example.com/gone".to_string(), href: "/gone".to_string(), anchor_text: "Gone".to_string() }])?;
+        let totals = RunTotals { total_urls: 1, errors: 1, redirect_issues: 0, external_checked: 0, skipped_by_robots: 0 };
+        storage.finish_run(run_id, &totals, "failed")?;
+        assert!(storage.finish_run(run_id + 1, &totals, "failed").is_err());
+
+        let data = memory.data();
+        assert_eq!(data.runs, vec![(run_id, "http://example.com/".to_string(), "{}".to_string(), "failed".to_string())]);
+        assert_eq!(data.pages[0].1.error_category, Some("not_found"));
+        assert_eq!((data.links[0].1.as_str(), data.links[0].2.target_url.as_str()), ("http://example.com/", "http://example.com/gone"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sqlite_storage_writes_in_batches() -> Result<(), BoxError> {
+        let conn = Connection::open_in_memory()?;
+        db::init_schema(&conn)?;
+        let mut storage = SqliteStorage::new(conn);
+        let count = |storage: &SqliteStorage| -> rusqlite::Result<usize> { storage.conn.query_row("SELECT COUNT(*) FROM pages", [], |row| row.get(0)) };
+
+        let run_id = storage.start_run("http://example.com/", "{}")?;
+        let page = |index: usize| PageRecord { check_url: format!("http://example.com/{}", index), normalized_url: format!("http://example.com/{}", index), domain: "example.com".to_string(), status: 200, external: false, error_category: None, error_message: None, redirect_count: 0, attempts: 1, elapsed_ms: 1, hops: 1 };
+        for index in 0..BATCH_SIZE - 1 {
+            storage.insert_page(run_id, page(index))?;
+        }
+        assert_eq!(count(&storage)?, 0);
+
+        // BATCH_SIZE 件目で1つのトランザクションとして書き込む
+        storage.insert_page(run_id, page(BATCH_SIZE))?;
+        assert_eq!(count(&storage)?, BATCH_SIZE);
+
+        // 実行の終了時には残りも書き込む
+        storage.insert_page(run_id, page(BATCH_SIZE + 1))?;
+        storage.finish_run(run_id, &RunTotals { total_urls: BATCH_SIZE + 1, errors: 0, redirect_issues: 0, external_checked: 0, skipped_by_robots: 0 }, "success")?;
+        assert_eq!(count(&storage)?, BATCH_SIZE + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sqlite_storage_resume_run() -> Result<(), BoxError> {
+        let conn = Connection::open_in_memory()?;
+        db::init_schema(&conn)?;
+        let mut storage = SqliteStorage::new(conn);
+
+        let run_id = storage.start_run("http://example.com/ http://example.org/", r#"{"depth":3,"concurrency":4}"#)?;
+        let record = |url: &str, kind, done| FrontierRecord { url: url.to_string(), kind, hops: 0, done };
+        storage.insert_frontier(run_id, vec![
+            record("http://example.com/", FrontierKind::Internal, false),
+            record("http://example.com/private", FrontierKind::Robots, true),
+            record("https://partner.example/", FrontierKind::External, false),
+        ])?;
+        storage.mark_done(run_id, "http://example.com/")?;
+        let page = |url: &str| PageRecord { check_url: url.to_string(), normalized_url: url.to_string(), domain: "example.com".to_string(), status: 200, external: false, error_category: None, error_message: None, redirect_count: 0, attempts: 1, elapsed_ms: 1, hops: 0 };
+        storage.insert_page(run_id, page("http://example.com/"))?;
+        // 結果は保存したが辿り終えていない
+        storage.insert_page(run_id, page("https://partner.example/"))?;
+
+        // running のままの実行は、別のプロセスがクロール中かもしれないので --force-resume がなければ続けない
+        assert!(storage.resume_run(run_id, r#"{"depth":3,"concurrency":4}"#, false).is_err());
+        let totals = RunTotals { total_urls: 1, errors: 0, redirect_issues: 0, external_checked: 0, skipped_by_robots: 1 };
+        storage.finish_run(run_id, &totals, "interrupted")?;
+
+        // 辿るURLを決める設定が違えば続きから実行しない（並列数などは変えてよい）
+        assert!(storage.resume_run(run_id, r#"{"depth":5,"concurrency":4}"#, false).is_err());
+        let resumed = storage.resume_run(run_id, r#"{"depth":3,"concurrency":8}"#, false)?;
+        assert_eq!(resumed.start_urls, vec!["http://example.com/", "http://example.org/"]);
+        assert_eq!(resumed.frontier, vec![
+            record("http://example.com/", FrontierKind::Internal, true),
+            record("http://example.com/private", FrontierKind::Robots, true),
+            record("https://partner.example/", FrontierKind::External, false),
+        ]);
+        // もう一度チェックするURLの結果は消す
+        assert_eq!(resumed.pages.iter().map(|page| page.check_url.as_str()).collect::<Vec<_>>(), vec!["http://example.com/"]);
+        let pages: usize = storage.conn.query_row("SELECT COUNT(*) FROM pages WHERE run_id = ?1", [run_id], |row| row.get(0))?;
+        assert_eq!(pages, 1);
+        // 続きから実行している間は running に戻る
+        assert!(storage.resume_run(run_id, "{}", false).is_err());
+        assert!(storage.resume_run(run_id, "{}", true).is_ok());
+
+        // 最後まで終わった実行は続きから実行できない
+        storage.finish_run(run_id, &totals, "success")?;
+        assert!(storage.resume_run(run_id, "{}", true).is_err());
+        assert!(storage.resume_run(run_id + 1, "{}", true).is_err());
+        Ok(())
+    }
+}